@@ -0,0 +1,24 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate aurora_rs;
+
+use aurora_rs::{decode_any_frame,Request,MeasurementType,CumulativeDuration};
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks which request the frame is answering, if any
+    if let Some((selector,packet)) = data.split_first() {
+        let request = match *selector % 9 {
+            0 => Some(Request::State),
+            1 => Some(Request::PartNumber),
+            2 => Some(Request::Version),
+            3 => Some(Request::Measure{type_: MeasurementType::GridPower, global: false}),
+            4 => Some(Request::SerialNumber),
+            5 => Some(Request::ManufactureDate),
+            6 => Some(Request::CumulativeEnergy(CumulativeDuration::Daily)),
+            7 => Some(Request::Raw{opcode: 0, payload: [0;6]}),
+            _ => None,
+        };
+        let _ = decode_any_frame(packet,request.as_ref());
+    }
+});
@@ -0,0 +1,83 @@
+#ifndef AURORA_H
+#define AURORA_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Length of a request frame, as written by `aurora_encode_frame`
+ */
+#define AURORA_REQUEST_LEN 10
+
+/**
+ * Length of a response frame, as read by `aurora_decode_frame`
+ */
+#define AURORA_RESPONSE_LEN 8
+
+/**
+ * The call succeeded
+ */
+#define AURORA_OK 0
+
+/**
+ * A required pointer argument was null
+ */
+#define AURORA_ERR_NULL -1
+
+/**
+ * The input buffer was shorter than a frame
+ */
+#define AURORA_ERR_SHORT -2
+
+/**
+ * The frame's crc did not match its contents
+ */
+#define AURORA_ERR_CRC -3
+
+/**
+ * Writes the 10 byte request frame for `opcode` with the 6 argument bytes from `args` to `out`
+ *
+ * # Safety
+ * `args` must point to 6 readable bytes, and `out` to 10 writable bytes.
+ */
+int32_t aurora_encode_frame(uint8_t address, uint8_t opcode, const uint8_t *args, uint8_t *out);
+
+/**
+ * Checks the crc of the 8 byte response frame in `buf`, and writes its 6 data bytes to `out`
+ *
+ * # Safety
+ * `buf` must point to `len` readable bytes, and `out` to 6 writable bytes.
+ */
+int32_t aurora_decode_frame(const uint8_t *buf, size_t len, uint8_t *out);
+
+/**
+ * Name of a transmission state code, or null if the code is unknown. The string is static.
+ */
+const char *aurora_transmission_state_name(uint8_t code);
+
+/**
+ * Name of a global state code, or null if the code is unknown. The string is static.
+ */
+const char *aurora_global_state_name(uint8_t code);
+
+/**
+ * Name of an inverter state code, or null if the code is unknown. The string is static.
+ */
+const char *aurora_inverter_state_name(uint8_t code);
+
+/**
+ * Name of a dc/dc converter state code, or null if the code is unknown. The string is static.
+ */
+const char *aurora_dcdc_state_name(uint8_t code);
+
+/**
+ * Name of a measurement type code, or null if the code is unknown. The string is static.
+ */
+const char *aurora_measurement_type_name(uint8_t code);
+
+#endif  /* AURORA_H */
@@ -0,0 +1,151 @@
+use std::io;
+
+use futures::{Async,Future,Poll};
+use tokio_service::Service;
+
+use {Command,Request,Response};
+
+/// A client for a single inverter on the bus, which fills in the address for every request
+///
+/// Created with `ForAddress::for_address`.
+#[derive(Clone,Debug)]
+pub struct AddressedClient<S>{
+    inner: S,
+    address: u8,
+}
+
+impl<S> AddressedClient<S>{
+    /// The bus address requests are sent to
+    pub fn address(&self) -> u8
+    {
+        self.address
+    }
+
+    pub fn get_ref(&self) -> &S
+    {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S
+    {
+        self.inner
+    }
+}
+
+impl<S> AddressedClient<S>
+    where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+{
+    /// Sends any command, resolving to its typed output
+    ///
+    /// For example `client.send(commands::Measure(MeasurementType::GridPower))` resolves to the
+    /// grid power as an `f32`. Commands with the same frame as one of `Request`'s are sent as that
+    /// request, and the rest as `Request::Raw`.
+    pub fn send<C: Command>(&self, cmd: C) -> SendCommand<S::Future,C>
+    {
+        let req = Request::from_frame(&cmd.frame(self.address));
+        SendCommand{
+            inner: self.inner.call((self.address,req)),
+            cmd,
+        }
+    }
+}
+
+/// Future returned by `AddressedClient::send`
+pub struct SendCommand<F,C>{
+    inner: F,
+    cmd: C,
+}
+
+impl<F,C> Future for SendCommand<F,C>
+    where F: Future<Item = Response, Error = io::Error>,
+          C: Command
+{
+    type Item = C::Output;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<C::Output,io::Error>
+    {
+        let response = try_ready!(self.inner.poll());
+        self.cmd.parse(response.data()).map(Async::Ready)
+    }
+}
+
+impl<S: Service<Request = (u8,Request)>> Service for AddressedClient<S>{
+    type Request = Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: Request) -> Self::Future
+    {
+        self.inner.call((self.address,req))
+    }
+}
+
+#[cfg(feature = "tower")]
+impl<S: ::tower_service::Service<(u8,Request)>> ::tower_service::Service<Request> for AddressedClient<S>{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(),S::Error>
+    {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request) -> S::Future
+    {
+        self.inner.call((self.address,req))
+    }
+}
+
+/// Extension for clients taking `(address, Request)` pairs, such as those created with `AuroraProto`
+pub trait ForAddress: Service<Request = (u8,Request)> + Sized{
+    /// Wraps this client so every request goes to the inverter at `address`
+    ///
+    /// To talk to several inverters over one connection, wrap clones (or an `Rc`) of the client.
+    fn for_address(self, address: u8) -> AddressedClient<Self>
+    {
+        AddressedClient{inner:self,address}
+    }
+}
+
+impl<S: Service<Request = (u8,Request)>> ForAddress for S{}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{measurement,service_fn};
+    use TransmissionState;
+
+    #[test]
+    fn send_parses_typed_output() {
+        use commands::Measure;
+        use MeasurementType;
+        let client = service_fn(|_,_| Ok(measurement(TransmissionState::Ok,1234.5))).for_address(2);
+        assert_eq!(client.send(Measure(MeasurementType::GridPower)).wait().unwrap(),1234.5);
+    }
+
+    #[test]
+    fn send_uses_the_matching_request() {
+        use commands::Measure;
+        use {GlobalState,MeasurementType};
+        let client = service_fn(|_,req| match req {
+            Request::Measure{type_,global: false} => Ok(Response::Measure{
+                trans: TransmissionState::Ok,
+                global: GlobalState::Run,
+                val: 230.0,
+                type_,
+            }),
+            other => panic!("unexpected request {:?}",other),
+        }).for_address(2);
+        assert_eq!(client.send(Measure(MeasurementType::GridVoltage)).wait().unwrap(),230.0);
+    }
+
+    #[test]
+    fn requests_go_to_the_address() {
+        let client = service_fn(|address,_| Ok(address)).for_address(2);
+        assert_eq!(client.address(),2);
+        assert_eq!(client.call(Request::State).wait().unwrap(),2);
+    }
+}
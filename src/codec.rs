@@ -0,0 +1,207 @@
+use std::io;
+use std::result::Result as StdResult;
+use std::sync::Arc;
+
+use tokio_codec::{Decoder,Encoder,Framed};
+use bytes::{BytesMut,BufMut};
+use tokio_io::{AsyncRead,AsyncWrite};
+use tokio_proto::pipeline::ClientProto;
+
+use {Command,Observer,Request,Response,decode_frame,RESPONSE_LEN};
+
+/// Codec for sending commands of type `C` and receiving their responses
+pub struct AuroraCodec<C = Request>{
+    last_request: Option<C>,
+    observer: Option<Arc<dyn Observer + Send + Sync>>,
+}
+
+impl<C> AuroraCodec<C>{
+    pub fn new() -> Self
+    {
+        AuroraCodec{last_request:None,observer:None}
+    }
+
+    /// Reports the frames this codec sends and receives to `observer`
+    pub fn with_observer(mut self, observer: Arc<dyn Observer + Send + Sync>) -> Self
+    {
+        self.observer = Some(observer);
+        self
+    }
+}
+
+impl<C> Default for AuroraCodec<C>{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<C: Command> Decoder for AuroraCodec<C>{
+    type Item = C::Output;
+    type Error = std::io::Error;
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error>
+    {
+        if buf.len() >= RESPONSE_LEN {
+            let packet = buf.split_to(RESPONSE_LEN);
+            //CRC check
+            let data = match decode_frame(&packet) {
+                Ok(data) => data,
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    ::tracing::warn!(packet = ?&packet[..], "crc mismatch in response frame");
+                    if let Some(ref observer) = self.observer {
+                        observer.crc_error(&packet);
+                    }
+                    return Err(e)
+                }
+            };
+            #[cfg(feature = "tracing")]
+            ::tracing::trace!(packet = ?&packet[..], "received response frame");
+            if let Some(ref observer) = self.observer {
+                observer.frame_received(&packet);
+            }
+            //Parse
+            if let Some(last) = self.last_request.take(){
+                last.parse(data).map(Some)
+            }else{
+                Err(io::Error::other("Got response without request"))
+            }
+
+        }else{
+            Ok(None)
+        }
+    }    
+}
+
+impl<C: Command> Encoder for AuroraCodec<C>{
+    type Item = (u8,C);
+    type Error = std::io::Error;
+    fn encode(&mut self, (addr,msg): Self::Item, buf: &mut BytesMut)-> Result<(),Self::Error>
+    {
+        let frame = msg.frame(addr);
+        self.last_request = Some(msg);
+        buf.put_slice(&frame.to_bytes());
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(address = frame.address, opcode = frame.opcode, payload = ?frame.payload, "sent request frame");
+        if let Some(ref observer) = self.observer {
+            observer.frame_sent(&frame);
+        }
+        Ok(())
+    }
+
+}
+
+pub struct AuroraProto;
+
+impl AuroraProto{
+    /// A protocol whose connections report their frames to `observer`
+    pub fn observed(observer: Arc<dyn Observer + Send + Sync>) -> ObservedProto
+    {
+        ObservedProto{observer}
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for AuroraProto{
+    type Request = (u8,Request);
+    type Response = Response;
+    type Transport = Framed<T, AuroraCodec>;
+    type BindTransport = StdResult<Self::Transport, io::Error>;
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(AuroraCodec::new().framed(io))
+    }
+}
+
+/// `AuroraProto` with an observer attached to every connection, created by `AuroraProto::observed`
+pub struct ObservedProto{
+    observer: Arc<dyn Observer + Send + Sync>,
+}
+
+impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for ObservedProto{
+    type Request = (u8,Request);
+    type Response = Response;
+    type Transport = Framed<T, AuroraCodec>;
+    type BindTransport = StdResult<Self::Transport, io::Error>;
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(AuroraCodec::new().with_observer(self.observer.clone()).framed(io))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+    use {encode_frame,encode_response,Frame};
+
+    /// An out of spec command, to check the codec works with commands besides `Request`
+    struct Echo(u8);
+
+    impl Command for Echo{
+        type Output = u8;
+
+        fn opcode(&self) -> u8
+        {
+            200
+        }
+
+        fn args(&self) -> [u8;6]
+        {
+            [self.0,0,0,0,0,0]
+        }
+
+        fn parse(&self, data: [u8;6]) -> io::Result<u8>
+        {
+            Ok(data[2])
+        }
+    }
+
+    #[test]
+    fn custom_commands_roundtrip() {
+        let mut codec = AuroraCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode((2,Echo(7)),&mut buf).unwrap();
+        assert_eq!(&buf[..],&encode_frame(2,200,[7,0,0,0,0,0])[..]);
+
+        let response = encode_response([0,6,9,0,0,0]);
+        let mut buf = BytesMut::from(&response[..6]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.put_slice(&response[6..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(),Some(9));
+    }
+
+    #[derive(Default)]
+    struct CountingObserver{
+        sent: AtomicUsize,
+        received: AtomicUsize,
+        crc_errors: AtomicUsize,
+    }
+
+    impl Observer for CountingObserver{
+        fn frame_sent(&self, _: &Frame) {
+            self.sent.fetch_add(1,Ordering::SeqCst);
+        }
+
+        fn frame_received(&self, _: &[u8]) {
+            self.received.fetch_add(1,Ordering::SeqCst);
+        }
+
+        fn crc_error(&self, _: &[u8]) {
+            self.crc_errors.fetch_add(1,Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn observer_sees_frames() {
+        let observer = Arc::new(CountingObserver::default());
+        let mut codec = AuroraCodec::new().with_observer(observer.clone());
+        let mut buf = BytesMut::new();
+        codec.encode((2,Echo(7)),&mut buf).unwrap();
+        let mut response = encode_response([0,6,9,0,0,0]);
+        codec.decode(&mut BytesMut::from(&response[..])).unwrap();
+        codec.encode((2,Echo(7)),&mut buf).unwrap();
+        response[7] ^= 0xFF;
+        assert!(codec.decode(&mut BytesMut::from(&response[..])).is_err());
+        assert_eq!(observer.sent.load(Ordering::SeqCst),2);
+        assert_eq!(observer.received.load(Ordering::SeqCst),1);
+        assert_eq!(observer.crc_errors.load(Ordering::SeqCst),1);
+    }
+}
@@ -0,0 +1,78 @@
+use std::io;
+
+use {Request,Response,Frame,REQUEST_LEN};
+
+/// A command that can be sent to an inverter
+///
+/// Implemented by `Request` for the commands this crate knows about, and open for
+/// implementing out of spec or model specific commands without changing `Request`.
+pub trait Command{
+    /// What the response to this command is parsed into
+    type Output;
+
+    /// The command byte
+    fn opcode(&self) -> u8;
+
+    /// The 6 argument bytes following the command byte, zeroed by default
+    fn args(&self) -> [u8;6]
+    {
+        [0;6]
+    }
+
+    /// Parses the 6 data bytes of a response to this command
+    fn parse(&self, data: [u8;6]) -> io::Result<Self::Output>;
+
+    /// The request frame sending this command to the inverter at `addr`
+    fn frame(&self, addr: u8) -> Frame
+    {
+        Frame::new(addr,self.opcode(),self.args())
+    }
+
+    /// Builds the request frame sending this command to the inverter at `addr`
+    fn encode(&self, addr: u8) -> [u8;REQUEST_LEN]
+    {
+        self.frame(addr).to_bytes()
+    }
+}
+
+impl Command for Request{
+    type Output = Response;
+
+    fn opcode(&self) -> u8
+    {
+        match *self{
+            Request::State => 50,
+            Request::PartNumber => 52,
+            Request::Version => 58,
+            Request::Measure{..} => 59,
+            Request::SerialNumber => 63,
+            Request::ManufactureDate => 65,
+            Request::CumulativeEnergy(_) => 78,
+            Request::Raw{opcode,..} => opcode,
+        }
+    }
+
+    fn args(&self) -> [u8;6]
+    {
+        let mut args = [0;6];
+        match *self{
+            Request::Measure{type_,global} => {
+                args[0] = type_ as u8;
+                args[1] = if global {1}else{0};
+            }
+            Request::CumulativeEnergy(duration) => {
+                args[0] = duration as u8;
+            }
+            Request::Raw{payload,..} => {
+                args = payload;
+            }
+            _ => {}
+        }
+        args
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<Response>
+    {
+        Response::decode(self,data)
+    }
+}
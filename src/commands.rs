@@ -0,0 +1,287 @@
+//! Typed commands, whose responses are parsed into just the data they carry
+//!
+//! Send these with `AddressedClient::send`, or with an `AuroraCodec` of your own.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use byteorder::{BigEndian,ByteOrder};
+
+use {Command,CumulativeDuration,MeasurementType};
+use {TransmissionState,GlobalState,InverterState,DcDcState};
+use code;
+
+/// Reads the inverter's state
+#[derive(Clone,Copy,Debug)]
+pub struct GetState;
+
+#[derive(Clone,Copy,Debug)]
+pub struct StateInfo{
+    pub trans: TransmissionState,
+    pub global: GlobalState,
+    pub inverter: InverterState,
+    pub dc1: DcDcState,
+    pub dc2: DcDcState,
+    pub alarm: u8,
+}
+
+impl Command for GetState{
+    type Output = StateInfo;
+
+    fn opcode(&self) -> u8
+    {
+        50
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<StateInfo>
+    {
+        Ok(StateInfo{
+            trans: code(data[0])?,
+            global: code(data[1])?,
+            inverter: code(data[2])?,
+            dc1: code(data[3])?,
+            dc2: code(data[4])?,
+            alarm: data[5],
+        })
+    }
+}
+
+/// Reads the inverter's part number
+#[derive(Clone,Copy,Debug)]
+pub struct GetPartNumber;
+
+impl Command for GetPartNumber{
+    type Output = [u8;6];
+
+    fn opcode(&self) -> u8
+    {
+        52
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<[u8;6]>
+    {
+        Ok(data)
+    }
+}
+
+/// Reads the inverter's version parameters
+#[derive(Clone,Copy,Debug)]
+pub struct GetVersion;
+
+#[derive(Clone,Copy,Debug)]
+pub struct VersionInfo{
+    pub trans: TransmissionState,
+    pub global: GlobalState,
+    pub par1: u8,
+    pub par2: u8,
+    pub par3: u8,
+    pub par4: u8,
+}
+
+impl Command for GetVersion{
+    type Output = VersionInfo;
+
+    fn opcode(&self) -> u8
+    {
+        58
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<VersionInfo>
+    {
+        Ok(VersionInfo{
+            trans: code(data[0])?,
+            global: code(data[1])?,
+            par1: data[2],
+            par2: data[3],
+            par3: data[4],
+            par4: data[5],
+        })
+    }
+}
+
+/// Reads a measurement from the inverter module being addressed
+#[derive(Clone,Copy,Debug)]
+pub struct Measure(pub MeasurementType);
+
+/// Reads a measurement summed over the whole inverter, rather than the module being addressed
+#[derive(Clone,Copy,Debug)]
+pub struct MeasureGlobal(pub MeasurementType);
+
+/// The inverter answered, but with a transmission state other than `Ok`
+///
+/// Commands fail with an `io::Error` wrapping this, use `Refused::from_io` to tell it apart from
+/// a transport error.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Refused{
+    pub state: TransmissionState,
+}
+
+impl Refused{
+    /// The refusal `err` wraps, if it is one
+    pub fn from_io(err: &io::Error) -> Option<Refused>
+    {
+        err.get_ref().and_then(|e| e.downcast_ref::<Refused>()).cloned()
+    }
+
+    /// Whether the inverter doesn't have the command or variable at all, rather than being
+    /// unable to answer right now
+    pub fn is_unsupported(&self) -> bool
+    {
+        matches!(self.state,TransmissionState::CommandNotImplemented | TransmissionState::VariableDoesNotExist)
+    }
+}
+
+impl fmt::Display for Refused{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f,"Inverter answered with {}",self.state.name())
+    }
+}
+
+impl Error for Refused{}
+
+impl From<Refused> for io::Error{
+    fn from(err: Refused) -> io::Error
+    {
+        io::Error::other(err)
+    }
+}
+
+/// Fails with `Refused` unless the transmission state says the rest of the response is valid
+fn check_transmission(data: [u8;6]) -> io::Result<()>
+{
+    match code(data[0])? {
+        TransmissionState::Ok => Ok(()),
+        state => Err(Refused{state}.into()),
+    }
+}
+
+fn parse_measurement(data: [u8;6]) -> io::Result<f32>
+{
+    check_transmission(data)?;
+    Ok(BigEndian::read_f32(&data[2..]))
+}
+
+impl Command for Measure{
+    type Output = f32;
+
+    fn opcode(&self) -> u8
+    {
+        59
+    }
+
+    fn args(&self) -> [u8;6]
+    {
+        [self.0 as u8,0,0,0,0,0]
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<f32>
+    {
+        parse_measurement(data)
+    }
+}
+
+impl Command for MeasureGlobal{
+    type Output = f32;
+
+    fn opcode(&self) -> u8
+    {
+        59
+    }
+
+    fn args(&self) -> [u8;6]
+    {
+        [self.0 as u8,1,0,0,0,0]
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<f32>
+    {
+        parse_measurement(data)
+    }
+}
+
+/// Reads the inverter's serial number
+#[derive(Clone,Copy,Debug)]
+pub struct GetSerialNumber;
+
+impl Command for GetSerialNumber{
+    type Output = [u8;6];
+
+    fn opcode(&self) -> u8
+    {
+        63
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<[u8;6]>
+    {
+        Ok(data)
+    }
+}
+
+/// Reads the week and year the inverter was manufactured
+#[derive(Clone,Copy,Debug)]
+pub struct GetManufactureDate;
+
+#[derive(Clone,Copy,Debug)]
+pub struct ManufactureDate{
+    pub week: [u8;2],
+    pub year: [u8;2],
+}
+
+impl Command for GetManufactureDate{
+    type Output = ManufactureDate;
+
+    fn opcode(&self) -> u8
+    {
+        65
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<ManufactureDate>
+    {
+        check_transmission(data)?;
+        Ok(ManufactureDate{
+            week: [data[2],data[3]],
+            year: [data[4],data[5]],
+        })
+    }
+}
+
+/// Reads the energy produced over a period, in Wh
+#[derive(Clone,Copy,Debug)]
+pub struct GetCumulativeEnergy(pub CumulativeDuration);
+
+impl Command for GetCumulativeEnergy{
+    type Output = u32;
+
+    fn opcode(&self) -> u8
+    {
+        78
+    }
+
+    fn args(&self) -> [u8;6]
+    {
+        [self.0 as u8,0,0,0,0,0]
+    }
+
+    fn parse(&self, data: [u8;6]) -> io::Result<u32>
+    {
+        check_transmission(data)?;
+        Ok(BigEndian::read_u32(&data[2..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refusals_are_distinguishable() {
+        let err = Measure(MeasurementType::Temp1).parse([51,6,0,0,0,0]).unwrap_err();
+        let refused = Refused::from_io(&err).unwrap();
+        assert_eq!(refused.state,TransmissionState::CommandNotImplemented);
+        assert!(refused.is_unsupported());
+        assert!(!Refused{state: TransmissionState::VariableNotAvailableRetry}.is_unsupported());
+        assert_eq!(Refused::from_io(&io::Error::new(io::ErrorKind::TimedOut,"no response")),None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Test doubles shared by the unit tests
+
+use std::collections::VecDeque;
+use std::io::{self,Read,Write};
+
+use byteorder::{BigEndian,ByteOrder};
+use futures::{task,Async,Poll};
+use futures::future::{self,FutureResult};
+use tokio_io::{AsyncRead,AsyncWrite};
+use tokio_service::Service;
+
+use {Request,Response,TransmissionState,REQUEST_LEN,RESPONSE_LEN};
+
+/// A service answering each `(address, request)` with the result of a closure
+#[derive(Clone)]
+pub struct ServiceFn<F>(F);
+
+pub fn service_fn<F,R>(f: F) -> ServiceFn<F>
+    where F: Fn(u8,Request) -> io::Result<R>
+{
+    ServiceFn(f)
+}
+
+impl<F,R> Service for ServiceFn<F>
+    where F: Fn(u8,Request) -> io::Result<R>
+{
+    type Request = (u8,Request);
+    type Response = R;
+    type Error = io::Error;
+    type Future = FutureResult<R,io::Error>;
+
+    fn call(&self, (address,req): (u8,Request)) -> Self::Future
+    {
+        future::result((self.0)(address,req))
+    }
+}
+
+/// The raw response to a measurement request
+pub fn measurement(state: TransmissionState, value: f32) -> Response
+{
+    let mut data = [state as u8,6,0,0,0,0];
+    BigEndian::write_f32(&mut data[2..],value);
+    Response::Raw(data)
+}
+
+/// An in memory connection, answering each request frame written to it with the next response
+pub struct Transport{
+    responses: VecDeque<[u8;RESPONSE_LEN]>,
+    written: Vec<u8>,
+    readable: VecDeque<u8>,
+}
+
+impl Transport{
+    pub fn new(responses: Vec<[u8;RESPONSE_LEN]>) -> Transport
+    {
+        Transport{responses: responses.into(), written: Vec::new(), readable: VecDeque::new()}
+    }
+}
+
+impl Read for Transport{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        if self.readable.is_empty() {
+            // there's nothing to wake the task once a request is written, so keep polling
+            task::current().notify();
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let len = buf.len().min(self.readable.len());
+        for (byte,read) in buf.iter_mut().zip(self.readable.drain(..len)) {
+            *byte = read;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for Transport{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.written.extend_from_slice(buf);
+        while self.written.len() >= REQUEST_LEN {
+            self.written.drain(..REQUEST_LEN);
+            if let Some(response) = self.responses.pop_front() {
+                self.readable.extend(response.iter());
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Transport{}
+
+impl AsyncWrite for Transport{
+    fn shutdown(&mut self) -> Poll<(),io::Error>
+    {
+        Ok(Async::Ready(()))
+    }
+}
@@ -0,0 +1,166 @@
+//! C compatible bindings for the frame layer and the state code tables
+//!
+//! The matching header is `include/aurora.h`, regenerate it with
+//! `cbindgen --config cbindgen.toml --output include/aurora.h` after changing this module.
+
+use std::convert::TryFrom;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+use {encode_frame,decode_frame,REQUEST_LEN,RESPONSE_LEN};
+use {TransmissionState,GlobalState,InverterState,DcDcState,MeasurementType};
+
+/// Length of a request frame, as written by `aurora_encode_frame`
+pub const AURORA_REQUEST_LEN: usize = 10;
+/// Length of a response frame, as read by `aurora_decode_frame`
+pub const AURORA_RESPONSE_LEN: usize = 8;
+
+// spelled out above so the header gets plain numbers, but they have to agree with the frame layer
+const _: () = assert!(AURORA_REQUEST_LEN == REQUEST_LEN && AURORA_RESPONSE_LEN == RESPONSE_LEN);
+
+/// The call succeeded
+pub const AURORA_OK: i32 = 0;
+/// A required pointer argument was null
+pub const AURORA_ERR_NULL: i32 = -1;
+/// The input buffer was shorter than a frame
+pub const AURORA_ERR_SHORT: i32 = -2;
+/// The frame's crc did not match its contents
+pub const AURORA_ERR_CRC: i32 = -3;
+
+/// Writes the 10 byte request frame for `opcode` with the 6 argument bytes from `args` to `out`
+///
+/// # Safety
+/// `args` must point to 6 readable bytes, and `out` to 10 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aurora_encode_frame(address: u8, opcode: u8, args: *const u8, out: *mut u8) -> i32
+{
+    if args.is_null() || out.is_null() {
+        return AURORA_ERR_NULL;
+    }
+    let mut arg_bytes = [0;6];
+    arg_bytes.copy_from_slice(slice::from_raw_parts(args,6));
+    let encoded = encode_frame(address,opcode,arg_bytes);
+    ptr::copy_nonoverlapping(encoded.as_ptr(),out,AURORA_REQUEST_LEN);
+    AURORA_OK
+}
+
+/// Checks the crc of the 8 byte response frame in `buf`, and writes its 6 data bytes to `out`
+///
+/// # Safety
+/// `buf` must point to `len` readable bytes, and `out` to 6 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aurora_decode_frame(buf: *const u8, len: usize, out: *mut u8) -> i32
+{
+    if buf.is_null() || out.is_null() {
+        return AURORA_ERR_NULL;
+    }
+    if len < AURORA_RESPONSE_LEN {
+        return AURORA_ERR_SHORT;
+    }
+    match decode_frame(slice::from_raw_parts(buf,len)) {
+        Ok(data) => {
+            ptr::copy_nonoverlapping(data.as_ptr(),out,data.len());
+            AURORA_OK
+        }
+        Err(_) => AURORA_ERR_CRC,
+    }
+}
+
+fn name_ptr(name: Option<&'static [u8]>) -> *const c_char
+{
+    name.map(|n| n.as_ptr() as *const c_char).unwrap_or(ptr::null())
+}
+
+/// Name of a transmission state code, or null if the code is unknown. The string is static.
+#[no_mangle]
+pub extern "C" fn aurora_transmission_state_name(code: u8) -> *const c_char
+{
+    name_ptr(TransmissionState::try_from(code).ok().map(|s| s.c_name()))
+}
+
+/// Name of a global state code, or null if the code is unknown. The string is static.
+#[no_mangle]
+pub extern "C" fn aurora_global_state_name(code: u8) -> *const c_char
+{
+    name_ptr(GlobalState::try_from(code).ok().map(|s| s.c_name()))
+}
+
+/// Name of an inverter state code, or null if the code is unknown. The string is static.
+#[no_mangle]
+pub extern "C" fn aurora_inverter_state_name(code: u8) -> *const c_char
+{
+    name_ptr(InverterState::try_from(code).ok().map(|s| s.c_name()))
+}
+
+/// Name of a dc/dc converter state code, or null if the code is unknown. The string is static.
+#[no_mangle]
+pub extern "C" fn aurora_dcdc_state_name(code: u8) -> *const c_char
+{
+    name_ptr(DcDcState::try_from(code).ok().map(|s| s.c_name()))
+}
+
+/// Name of a measurement type code, or null if the code is unknown. The string is static.
+#[no_mangle]
+pub extern "C" fn aurora_measurement_type_name(code: u8) -> *const c_char
+{
+    name_ptr(MeasurementType::try_from(code).ok().map(|s| s.c_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use encode_response;
+
+    #[test]
+    fn null_pointers_are_rejected() {
+        let mut out = [0;AURORA_REQUEST_LEN];
+        unsafe {
+            assert_eq!(aurora_encode_frame(2,50,ptr::null(),out.as_mut_ptr()),AURORA_ERR_NULL);
+            assert_eq!(aurora_encode_frame(2,50,[0;6].as_ptr(),ptr::null_mut()),AURORA_ERR_NULL);
+            assert_eq!(aurora_decode_frame(ptr::null(),AURORA_RESPONSE_LEN,out.as_mut_ptr()),AURORA_ERR_NULL);
+            assert_eq!(aurora_decode_frame(out.as_ptr(),AURORA_RESPONSE_LEN,ptr::null_mut()),AURORA_ERR_NULL);
+        }
+    }
+
+    #[test]
+    fn short_and_corrupt_frames_are_rejected() {
+        let mut response = encode_response([0,6,2,0,0,0]);
+        let mut out = [0;6];
+        unsafe {
+            assert_eq!(aurora_decode_frame(response.as_ptr(),AURORA_RESPONSE_LEN - 1,out.as_mut_ptr()),AURORA_ERR_SHORT);
+            response[2] ^= 0xFF;
+            assert_eq!(aurora_decode_frame(response.as_ptr(),response.len(),out.as_mut_ptr()),AURORA_ERR_CRC);
+        }
+    }
+
+    #[test]
+    fn frames_roundtrip() {
+        let args = [3,1,0,0,0,0];
+        let mut request = [0;AURORA_REQUEST_LEN];
+        let mut data = [0;6];
+        let response = encode_response([0,6,1,2,3,4]);
+        unsafe {
+            assert_eq!(aurora_encode_frame(2,59,args.as_ptr(),request.as_mut_ptr()),AURORA_OK);
+            assert_eq!(aurora_decode_frame(response.as_ptr(),response.len(),data.as_mut_ptr()),AURORA_OK);
+        }
+        assert_eq!(request,encode_frame(2,59,args));
+        assert_eq!(data,[0,6,1,2,3,4]);
+    }
+
+    #[test]
+    fn names_are_nul_terminated() {
+        let name = |ptr: *const c_char| unsafe { CStr::from_ptr(ptr) }.to_str().unwrap();
+        assert_eq!(name(aurora_global_state_name(6)),"Run");
+        assert_eq!(name(aurora_transmission_state_name(51)),"CommandNotImplemented");
+        assert_eq!(name(aurora_measurement_type_name(3)),"GridPower");
+        assert!(!aurora_inverter_state_name(2).is_null());
+        assert!(!aurora_dcdc_state_name(2).is_null());
+        assert!(aurora_global_state_name(255).is_null());
+        assert!(aurora_measurement_type_name(0).is_null());
+        assert!(aurora_inverter_state_name(255).is_null());
+        assert!(aurora_dcdc_state_name(255).is_null());
+        assert!(aurora_transmission_state_name(1).is_null());
+    }
+}
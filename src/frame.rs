@@ -0,0 +1,160 @@
+use std::io;
+
+use crc16::State;
+
+type AuroraCrc = crc16::X_25;
+
+#[inline]
+fn lo(val: u16) -> u8
+{
+    (val & 0xFF) as u8
+}
+
+#[inline]
+fn hi(val: u16) -> u8
+{
+    ((val >> 8) & 0xFF) as u8
+}
+
+fn crc(data: &[u8]) -> u16
+{
+    State::<AuroraCrc>::calculate(data)
+}
+
+/// Length of a request frame: address, command, 6 argument bytes and the crc
+pub const REQUEST_LEN: usize = 10;
+/// Length of a response frame: 6 data bytes and the crc
+pub const RESPONSE_LEN: usize = 8;
+
+/// A request frame as it appears on the wire
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct Frame{
+    pub address: u8,
+    pub opcode: u8,
+    pub payload: [u8;6],
+    pub crc: u16,
+}
+
+impl Frame{
+    /// Builds a frame for the inverter at `address`, calculating its crc
+    pub fn new(address: u8, opcode: u8, payload: [u8;6]) -> Frame
+    {
+        let mut frame = Frame{address,opcode,payload,crc:0};
+        frame.crc = frame.expected_crc();
+        frame
+    }
+
+    fn expected_crc(&self) -> u16
+    {
+        let mut data = [0;8];
+        data[0] = self.address;
+        data[1] = self.opcode;
+        data[2..8].copy_from_slice(&self.payload);
+        crc(&data)
+    }
+
+    /// Whether the crc matches the rest of the frame
+    pub fn is_valid(&self) -> bool
+    {
+        self.crc == self.expected_crc()
+    }
+
+    pub fn to_bytes(&self) -> [u8;REQUEST_LEN]
+    {
+        let mut encoded = [0;REQUEST_LEN];
+        encoded[0] = self.address;
+        encoded[1] = self.opcode;
+        encoded[2..8].copy_from_slice(&self.payload);
+        encoded[8] = lo(self.crc);
+        encoded[9] = hi(self.crc);
+        encoded
+    }
+
+    /// Parses a frame from the start of `bytes`, without checking its crc
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> io::Result<Frame>
+    {
+        if bytes.len() < REQUEST_LEN {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,"Request frame too short"))
+        }
+        let mut payload = [0;6];
+        payload.copy_from_slice(&bytes[2..8]);
+        Ok(Frame{
+            address: bytes[0],
+            opcode: bytes[1],
+            payload,
+            crc: u16::from(bytes[8]) | (u16::from(bytes[9]) << 8),
+        })
+    }
+
+    /// Parses a frame from the start of `bytes`, failing if its crc doesn't match
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Frame>
+    {
+        let frame = Frame::from_bytes_unchecked(bytes)?;
+        if !frame.is_valid() {
+            return Err(io::Error::other("CRC mismatch"))
+        }
+        Ok(frame)
+    }
+}
+
+/// Builds a request frame for the inverter at `addr`, appending the crc
+pub fn encode_frame(addr: u8, opcode: u8, args: [u8;6]) -> [u8;REQUEST_LEN]
+{
+    Frame::new(addr,opcode,args).to_bytes()
+}
+
+/// Builds a response frame carrying `data`, appending the crc, as an inverter would
+pub fn encode_response(data: [u8;6]) -> [u8;RESPONSE_LEN]
+{
+    let crc = crc(&data);
+    let mut encoded = [0;RESPONSE_LEN];
+    encoded[0..6].copy_from_slice(&data);
+    encoded[6] = lo(crc);
+    encoded[7] = hi(crc);
+    encoded
+}
+
+/// Checks the crc of a response frame, and returns its 6 data bytes
+pub fn decode_frame(packet: &[u8]) -> io::Result<[u8;6]>
+{
+    if packet.len() < RESPONSE_LEN {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof,"Response frame too short"))
+    }
+    let data = &packet[0..6];
+    let crc_val = &packet[6..8];
+    let crc_calc = crc(data);
+    if crc_val != [lo(crc_calc),hi(crc_calc)] {
+        return Err(io::Error::other("CRC mismatch"))
+    }
+    let mut out = [0;6];
+    out.copy_from_slice(data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_roundtrip_through_bytes() {
+        let frame = Frame::new(2,59,[3,0,0,0,0,0]);
+        assert!(frame.is_valid());
+        let bytes = frame.to_bytes();
+        assert_eq!(Frame::from_bytes(&bytes).unwrap(),frame);
+
+        let mut corrupt = bytes;
+        corrupt[3] ^= 0xFF;
+        assert!(Frame::from_bytes(&corrupt).is_err());
+        assert!(!Frame::from_bytes_unchecked(&corrupt).unwrap().is_valid());
+        assert!(Frame::from_bytes(&bytes[..9]).is_err());
+    }
+
+    #[test]
+    fn decode_frame_checks_crc() {
+        let mut response = encode_response([2,50,0,0,0,0]);
+        assert_eq!(decode_frame(&response).unwrap(),[2,50,0,0,0,0]);
+        response[2] ^= 0xFF;
+        assert!(decode_frame(&response).is_err());
+        assert!(decode_frame(&response[..4]).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use std::io;
+use std::sync::{Arc,OnceLock};
+use std::time::{Duration,Instant};
+
+use futures::{Async,Future,Poll,Stream};
+use futures::sync::{mpsc,oneshot};
+use tokio_core::reactor::Handle;
+use tokio_service::Service;
+use tokio_timer::{self,Sleep,Timer};
+
+use {ConnectionStats,Observer,Request,Response,Stats};
+
+type Message = ((u8,Request),oneshot::Sender<io::Result<Response>>);
+
+/// A cheap, cloneable handle to a connection owned by a `Dispatcher`
+///
+/// Requests from every clone are queued and sent one at a time in the order they were made, as
+/// the bus is half duplex. Implements `Service`, so it can be wrapped with `for_address`.
+#[derive(Clone,Debug)]
+pub struct ClientHandle{
+    tx: mpsc::UnboundedSender<Message>,
+    stats: Arc<ConnectionStats>,
+}
+
+impl ClientHandle{
+    /// Creates a handle to `service` along with the dispatcher which must be run for requests to
+    /// make progress
+    ///
+    /// The handle's `stats` only cover round trip times and timeouts, use `with_stats` to count
+    /// frames and crc errors too.
+    pub fn new<S>(service: S) -> (ClientHandle,Dispatcher<S>)
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+    {
+        ClientHandle::with_stats(service,Arc::new(ConnectionStats::new()))
+    }
+
+    /// Like `new`, but recording round trip times and timeouts into `stats`
+    ///
+    /// Pass the same `stats` to `AuroraProto::observed` to count frames and crc errors too.
+    pub fn with_stats<S>(service: S, stats: Arc<ConnectionStats>) -> (ClientHandle,Dispatcher<S>)
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+    {
+        ClientHandle::dispatch(service,stats.clone(),stats)
+    }
+
+    /// Like `with_stats`, but also reporting the outcome of every request to `observer`
+    ///
+    /// A request counts as timed out when `service` fails it with `io::ErrorKind::TimedOut`, for
+    /// example from a timeout layered under the handle.
+    pub fn with_observer<S>(service: S, stats: Arc<ConnectionStats>, observer: Arc<dyn Observer + Send + Sync>)
+        -> (ClientHandle,Dispatcher<S>)
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+    {
+        ClientHandle::dispatch(service,stats.clone(),Arc::new(Both(stats,observer)))
+    }
+
+    fn dispatch<S>(service: S, stats: Arc<ConnectionStats>, observer: Arc<dyn Observer + Send + Sync>)
+        -> (ClientHandle,Dispatcher<S>)
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+    {
+        let (tx,rx) = mpsc::unbounded();
+        (ClientHandle{tx,stats},Dispatcher{
+            service,
+            observer,
+            rx,
+            in_flight: None,
+            queued: None,
+            spacing: None,
+            last_done: None,
+            delay: None,
+        })
+    }
+
+    /// Creates a handle to `service`, spawning its dispatcher on `handle`
+    pub fn spawn<S>(service: S, handle: &Handle) -> ClientHandle
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error> + 'static
+    {
+        let (client,dispatcher) = ClientHandle::new(service);
+        handle.spawn(dispatcher);
+        client
+    }
+
+    /// Like `spawn`, but leaving at least `spacing` between the end of one request and the start
+    /// of the next, see `Dispatcher::with_min_spacing`
+    pub fn spawn_with_min_spacing<S>(service: S, spacing: Duration, handle: &Handle) -> ClientHandle
+        where S: Service<Request = (u8,Request), Response = Response, Error = io::Error> + 'static
+    {
+        let (client,dispatcher) = ClientHandle::new(service);
+        handle.spawn(dispatcher.with_min_spacing(spacing));
+        client
+    }
+
+    /// Statistics about the connection so far
+    ///
+    /// Frame counts are only filled in when the stats are shared with the codec, see `with_stats`.
+    pub fn stats(&self) -> Stats
+    {
+        self.stats.snapshot()
+    }
+}
+
+impl Service for ClientHandle{
+    type Request = (u8,Request);
+    type Response = Response;
+    type Error = io::Error;
+    type Future = ResponseFuture;
+
+    fn call(&self, req: (u8,Request)) -> ResponseFuture
+    {
+        let (tx,rx) = oneshot::channel();
+        // if the dispatcher has gone the sender is dropped, and the receiver reports it
+        let _ = self.tx.unbounded_send((req,tx));
+        ResponseFuture{rx}
+    }
+}
+
+#[cfg(feature = "tower")]
+impl ::tower_service::Service<(u8,Request)> for ClientHandle{
+    type Response = Response;
+    type Error = io::Error;
+    type Future = ResponseFuture;
+
+    /// Always ready, requests are queued until the dispatcher gets to them
+    fn poll_ready(&mut self) -> Poll<(),io::Error>
+    {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: (u8,Request)) -> ResponseFuture
+    {
+        Service::call(self,req)
+    }
+}
+
+/// Reports the outcome of each request to a handle's stats as well as an application's observer
+struct Both(Arc<ConnectionStats>,Arc<dyn Observer + Send + Sync>);
+
+impl Observer for Both{
+    fn response_received(&self, address: u8, latency: Duration)
+    {
+        self.0.response_received(address,latency);
+        self.1.response_received(address,latency);
+    }
+
+    fn timeout(&self, address: u8)
+    {
+        self.0.timeout(address);
+        self.1.timeout(address);
+    }
+}
+
+/// Future returned by `ClientHandle::call`
+pub struct ResponseFuture{
+    rx: oneshot::Receiver<io::Result<Response>>,
+}
+
+impl Future for ResponseFuture{
+    type Item = Response;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Response,io::Error>
+    {
+        match self.rx.poll() {
+            Ok(Async::Ready(res)) => res.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe,"Connection dispatcher stopped")),
+        }
+    }
+}
+
+/// Owns a connection, and feeds it the requests made through its `ClientHandle`s
+///
+/// Resolves once every handle has been dropped and the queue is drained.
+pub struct Dispatcher<S: Service>{
+    service: S,
+    observer: Arc<dyn Observer + Send + Sync>,
+    rx: mpsc::UnboundedReceiver<Message>,
+    in_flight: Option<InFlight<S::Future>>,
+    queued: Option<Message>,
+    spacing: Option<Duration>,
+    last_done: Option<Instant>,
+    delay: Option<Sleep>,
+}
+
+struct InFlight<F>{
+    fut: F,
+    tx: oneshot::Sender<io::Result<Response>>,
+    address: u8,
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    span: ::tracing::Span,
+}
+
+impl<S: Service> Dispatcher<S>{
+    /// Leaves at least `spacing` between the end of one request and the start of the next
+    ///
+    /// Some inverter firmwares drop frames that arrive too soon after their last response.
+    pub fn with_min_spacing(mut self, spacing: Duration) -> Self
+    {
+        self.spacing = Some(spacing);
+        self
+    }
+
+    fn start_delay(&mut self)
+    {
+        let last_done = match self.last_done {
+            Some(last_done) => last_done,
+            None => return,
+        };
+        if let Some(spacing) = self.spacing {
+            let elapsed = last_done.elapsed();
+            if elapsed < spacing {
+                // the timer can fire up to a tick early
+                self.delay = Some(spacing_timer().sleep(spacing - elapsed + SPACING_TICK));
+            }
+        }
+    }
+}
+
+const SPACING_TICK: Duration = Duration::from_millis(10);
+
+/// The timer shared by every dispatcher's request spacing, so each doesn't start its own thread
+fn spacing_timer() -> &'static Timer
+{
+    static TIMER: OnceLock<Timer> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        // the spacing is up to the application, so don't limit how long a sleep can be
+        tokio_timer::wheel().tick_duration(SPACING_TICK).max_timeout(Duration::MAX).build()
+    })
+}
+
+impl<S> Future for Dispatcher<S>
+    where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(),()>
+    {
+        loop {
+            if let Some(mut in_flight) = self.in_flight.take() {
+                let res = {
+                    #[cfg(feature = "tracing")]
+                    let _entered = in_flight.span.enter();
+                    match in_flight.fut.poll() {
+                        Ok(Async::NotReady) => None,
+                        Ok(Async::Ready(res)) => Some(Ok(res)),
+                        Err(e) => Some(Err(e)),
+                    }
+                };
+                let res = match res {
+                    Some(res) => res,
+                    None => {
+                        self.in_flight = Some(in_flight);
+                        return Ok(Async::NotReady);
+                    }
+                };
+                let latency = in_flight.started.elapsed();
+                // other failures, like crc errors or a dropped connection, didn't get an answer to
+                // time, and aren't timeouts either
+                match res {
+                    Ok(_) => self.observer.response_received(in_flight.address,latency),
+                    Err(ref e) if e.kind() == io::ErrorKind::TimedOut => self.observer.timeout(in_flight.address),
+                    Err(_) => {}
+                }
+                #[cfg(feature = "tracing")]
+                {
+                    let _entered = in_flight.span.enter();
+                    let latency_ms = latency.as_secs_f64() * 1000.0;
+                    match res {
+                        Ok(_) => ::tracing::debug!(latency_ms, "request completed"),
+                        Err(ref e) => ::tracing::warn!(latency_ms, error = %e, "request failed"),
+                    }
+                }
+                let _ = in_flight.tx.send(res);
+                self.last_done = Some(Instant::now());
+            }
+            if self.queued.is_none() {
+                match try_ready!(self.rx.poll()) {
+                    Some(msg) => {
+                        self.queued = Some(msg);
+                        self.start_delay();
+                    }
+                    None => return Ok(Async::Ready(())),
+                }
+            }
+            if let Some(ref mut delay) = self.delay {
+                // a timer error only costs the spacing, not the connection
+                if let Ok(Async::NotReady) = delay.poll() {
+                    return Ok(Async::NotReady);
+                }
+            }
+            self.delay = None;
+            if let Some((req,tx)) = self.queued.take() {
+                let address = req.0;
+                // nobody is waiting for this one any more, so don't tie up the bus with it
+                if tx.is_canceled() {
+                    continue;
+                }
+                #[cfg(feature = "tracing")]
+                let span = ::tracing::debug_span!("aurora_request", address = req.0, request = ?req.1);
+                let fut = {
+                    #[cfg(feature = "tracing")]
+                    let _entered = span.enter();
+                    self.service.call(req)
+                };
+                self.in_flight = Some(InFlight{
+                    fut,
+                    tx,
+                    address,
+                    started: Instant::now(),
+                    #[cfg(feature = "tracing")]
+                    span,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+    use futures::future;
+    use tokio_core::reactor::Core;
+    use tokio_proto::BindClient;
+    use fake::{measurement,service_fn,ServiceFn,Transport};
+    use {encode_response,AuroraProto,ForAddress,MeasurementType,TransmissionState,RESPONSE_LEN};
+
+    /// Answers every request with its serial number, counting the requests it has seen
+    fn counting_inverter(count: Rc<Cell<u8>>) -> ServiceFn<impl Fn(u8,Request) -> io::Result<Response>>
+    {
+        service_fn(move |_,_| {
+            count.set(count.get() + 1);
+            Ok(Response::SerialNumber([count.get();6]))
+        })
+    }
+
+    #[test]
+    fn handles_share_one_connection() {
+        let mut core = Core::new().unwrap();
+        let count = Rc::new(Cell::new(0));
+        let client = ClientHandle::spawn(counting_inverter(count.clone()),&core.handle());
+        let first = client.clone().for_address(2).call(Request::SerialNumber);
+        let second = client.for_address(3).call(Request::SerialNumber);
+        let (a,b) = core.run(first.join(second)).unwrap();
+        match (a,b) {
+            (Response::SerialNumber(a),Response::SerialNumber(b)) => assert_eq!((a[0],b[0]),(1,2)),
+            other => panic!("unexpected responses {:?}",other),
+        }
+        assert_eq!(count.get(),2);
+    }
+
+    #[test]
+    fn requests_are_spaced_out() {
+        let mut core = Core::new().unwrap();
+        let count = Rc::new(Cell::new(0));
+        let spacing = Duration::from_millis(100);
+        let client = ClientHandle::spawn_with_min_spacing(counting_inverter(count),spacing,&core.handle());
+        let start = Instant::now();
+        let requests = (0..3).map(|_| client.call((2,Request::SerialNumber))).collect::<Vec<_>>();
+        core.run(future::join_all(requests)).unwrap();
+        assert!(start.elapsed() >= spacing * 2);
+    }
+
+    #[test]
+    fn stats_count_latency_and_timeouts() {
+        let mut core = Core::new().unwrap();
+        let count = Rc::new(Cell::new(0));
+        let client = ClientHandle::spawn(counting_inverter(count),&core.handle());
+        core.run(client.call((2,Request::SerialNumber))).unwrap();
+        assert!(client.stats().latency_p50.is_some());
+
+        let unplugged = service_fn(|_,_| Err(io::Error::new(io::ErrorKind::TimedOut,"no response")));
+        let client = ClientHandle::spawn(unplugged,&core.handle());
+        assert!(core.run(client.call((2,Request::SerialNumber))).is_err());
+        assert_eq!(client.stats().timeouts,1);
+        assert_eq!(client.stats().latency_p50,None);
+
+        let broken = service_fn(|_,_| Err(io::Error::new(io::ErrorKind::BrokenPipe,"gone")));
+        let client = ClientHandle::spawn(broken,&core.handle());
+        assert!(core.run(client.call((2,Request::SerialNumber))).is_err());
+        assert_eq!((client.stats().round_trips,client.stats().timeouts),(0,0));
+    }
+
+    #[test]
+    fn long_spacing_is_allowed() {
+        let mut sleep = spacing_timer().sleep(Duration::from_secs(3600));
+        let polled = future::lazy(|| Ok::<_,()>(sleep.poll())).wait().unwrap();
+        assert!(matches!(polled,Ok(Async::NotReady)));
+    }
+
+    /// Collects the addresses of timed out requests
+    #[derive(Default)]
+    struct Timeouts(Mutex<Vec<u8>>);
+
+    impl Observer for Timeouts{
+        fn timeout(&self, address: u8)
+        {
+            self.0.lock().unwrap().push(address);
+        }
+    }
+
+    #[test]
+    fn observers_see_timeouts() {
+        let mut core = Core::new().unwrap();
+        let timeouts = Arc::new(Timeouts::default());
+        let unplugged = service_fn(|_,_| Err(io::Error::new(io::ErrorKind::TimedOut,"no response")));
+        let (client,dispatcher) = ClientHandle::with_observer(unplugged,Arc::new(ConnectionStats::new()),timeouts.clone());
+        core.handle().spawn(dispatcher);
+        assert!(core.run(client.clone().for_address(7).call(Request::State)).is_err());
+        assert_eq!(*timeouts.0.lock().unwrap(),vec![7]);
+        assert_eq!(client.stats().timeouts,1);
+    }
+
+    #[test]
+    fn stats_shared_with_the_codec_count_frames() {
+        let mut core = Core::new().unwrap();
+        let good = encode_response(measurement(TransmissionState::Ok,230.0).data());
+        let mut corrupt = good;
+        corrupt[7] ^= 0xFF;
+        let stats = Arc::new(ConnectionStats::new());
+        let service = AuroraProto::observed(stats.clone()).bind_client(&core.handle(),Transport::new(vec![good,corrupt]));
+        let (client,dispatcher) = ClientHandle::with_stats(service,stats);
+        core.handle().spawn(dispatcher);
+        let req = Request::Measure{type_: MeasurementType::GridVoltage, global: false};
+        core.run(client.call((2,req.clone()))).unwrap();
+        assert!(core.run(client.call((2,req))).is_err());
+        let stats = client.stats();
+        assert_eq!((stats.requests,stats.responses,stats.crc_errors),(2,1,1));
+        assert_eq!(stats.bytes_in,2 * RESPONSE_LEN as u64);
+        assert_eq!(stats.crc_error_rate(),Some(0.5));
+    }
+}
@@ -3,46 +3,115 @@
 //! Spec can be found at:
 //! [xilinx forum](https://forums.xilinx.com/xlnx/attachments/xlnx/CONN/10023/1/AuroraCommunicationProtocol_4_2.pdf)
 
+#[cfg(feature = "tokio")]
+#[macro_use]
 extern crate futures;
+#[cfg(feature = "tokio")]
 extern crate tokio_core;
+#[cfg(feature = "tokio")]
 extern crate tokio_proto;
+#[cfg(feature = "tokio")]
 extern crate tokio_service;
+#[cfg(feature = "tokio")]
 extern crate tokio_io;
+#[cfg(feature = "tokio")]
+extern crate tokio_codec;
+#[cfg(feature = "tokio")]
+extern crate tokio_timer;
+#[cfg(feature = "tokio")]
+extern crate bytes;
+#[cfg(feature = "tower")]
+extern crate tower_service;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(all(test, feature = "chrono"))]
+extern crate chrono_tz;
 extern crate crc16;
 extern crate byteorder;
-extern crate bytes;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "test-support")]
 #[macro_use]
-extern crate enum_primitive;
+extern crate arbitrary;
+#[cfg(feature = "test-support")]
+extern crate proptest;
+// pyo3's, arbitrary's and tracing's macros refer to `::core`, which needs an explicit extern crate on the 2015 edition
+#[cfg(any(feature = "python", feature = "test-support", feature = "tracing"))]
+extern crate core;
 
 mod state_codes;
 pub use state_codes::*;
+mod frame;
+pub use frame::*;
+mod observer;
+pub use observer::*;
+mod stats;
+pub use stats::*;
+mod command;
+pub use command::*;
+pub mod commands;
+#[cfg(feature = "tokio")]
+mod codec;
+#[cfg(feature = "tokio")]
+pub use codec::*;
+#[cfg(feature = "tokio")]
+mod client;
+#[cfg(feature = "tokio")]
+pub use client::*;
+#[cfg(feature = "tokio")]
+mod handle;
+#[cfg(feature = "tokio")]
+pub use handle::*;
+#[cfg(feature = "tokio")]
+mod sniffer;
+#[cfg(feature = "tokio")]
+pub use sniffer::*;
+#[cfg(feature = "tokio")]
+mod snapshot;
+#[cfg(feature = "tokio")]
+pub use snapshot::*;
+#[cfg(all(test, feature = "tokio"))]
+mod fake;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "chrono")]
+pub mod time;
 
 
+use std::convert::TryFrom;
 use std::io;
-use std::result::Result as StdResult;
 
-use tokio_io::codec::{Decoder,Encoder,Framed};
-use bytes::{BytesMut,BufMut,IntoBuf,Buf};
-use tokio_io::{AsyncRead,AsyncWrite};
-use tokio_proto::pipeline::ClientProto;
-use crc16::State;
 use byteorder::{BigEndian,ByteOrder};
-use enum_primitive::FromPrimitive;
 
 
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum CumulativeDuration{
     Daily = 0,
     Weekly = 1,
     //2 intentionally skipped
-    Montly = 3,
+    Monthly = 3,
     Yearly = 4,
     Total = 5,
     SinceReset = 6,
 }
 
-#[derive(Debug)]
+/// cbindgen:ignore
+impl CumulativeDuration{
+    #[deprecated(note = "renamed to `Monthly`")]
+    #[allow(non_upper_case_globals)]
+    pub const Montly: CumulativeDuration = CumulativeDuration::Monthly;
+}
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum Request{
     State,
     PartNumber,
@@ -56,9 +125,15 @@ pub enum Request{
     //Some skipped
     CumulativeEnergy(CumulativeDuration),
     //TODO: MORE...
+    /// Any other command, answered with `Response::Raw`
+    Raw{
+        opcode: u8,
+        payload: [u8;6]
+    },
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum Response{
     State{
         trans: TransmissionState,
@@ -98,151 +173,233 @@ pub enum Response{
         duration: CumulativeDuration
     },
     //TODO: MORE...
+    /// The undecoded data bytes of the response to a `Request::Raw`
+    Raw([u8;6]),
 }
 
-#[inline]
-fn lo(val: u16) -> u8
-{
-    (val & 0xFF) as u8
-}
+impl TryFrom<u8> for CumulativeDuration{
+    type Error = UnknownCode;
 
-#[inline]
-fn hi(val: u16) -> u8
-{
-    ((val >> 8) & 0xFF) as u8
+    fn try_from(code: u8) -> Result<CumulativeDuration,UnknownCode>
+    {
+        match code{
+            0 => Ok(CumulativeDuration::Daily),
+            1 => Ok(CumulativeDuration::Weekly),
+            3 => Ok(CumulativeDuration::Monthly),
+            4 => Ok(CumulativeDuration::Yearly),
+            5 => Ok(CumulativeDuration::Total),
+            6 => Ok(CumulativeDuration::SinceReset),
+            _ => Err(UnknownCode{kind: "CumulativeDuration", code}),
+        }
+    }
 }
 
-pub struct AuroraCodec{
-    last_request: Option<Request>,
+impl From<CumulativeDuration> for u8{
+    fn from(val: CumulativeDuration) -> u8
+    {
+        val as u8
+    }
 }
 
-impl Decoder for AuroraCodec{
-    type Item = Response;
-    type Error = std::io::Error;
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error>
+impl Request{
+    /// Builds the request frame asking the inverter at `addr` for this request
+    pub fn encode(&self, addr: u8) -> [u8;REQUEST_LEN]
     {
-        if buf.len() >= 8 {
-            let packet = buf.split_to(9).into_buf();
-            //CRC check
-            let data = &packet.bytes()[0..6];
-            let crc_val = &packet.bytes()[6..8];
-            let crc_calc = State::<AuroraCrc>::calculate(data);
-            if crc_val != &[lo(crc_calc),hi(crc_calc)] {
-                return Err(io::Error::new(io::ErrorKind::Other,"CRC mismatch"))
-            }
-            //Parse
-            if let Some(last) = self.last_request.take(){
-                Ok(Some(match last {
-                    Request::State => Response::State{
-                        trans: TransmissionState::from_u8(data[0]).unwrap(),
-                        global: GlobalState::from_u8(data[1]).unwrap(),
-                        inverter: InverterState::from_u8(data[2]).unwrap(),
-                        dc1: DcDcState::from_u8(data[3]).unwrap(),
-                        dc2: DcDcState::from_u8(data[4]).unwrap(),
-                        alarm: data[5]
-                    },
-                    Request::PartNumber => Response::PartNumber([data[0],data[1],data[2],data[3],data[4],data[5]]),
-                    Request::Version => Response::Version{
-                        trans: TransmissionState::from_u8(data[0]).unwrap(),
-                        global: GlobalState::from_u8(data[1]).unwrap(),
-                        par1: data[2],
-                        par2: data[3],
-                        par3: data[4],
-                        par4: data[5]
-                    },
-                    Request::Measure{type_,..} => Response::Measure{
-                        trans: TransmissionState::from_u8(data[0]).unwrap(),
-                        global: GlobalState::from_u8(data[1]).unwrap(),
-                        val: BigEndian::read_f32(&data[2..]),
-                        type_: type_
-                    },
-                    Request::SerialNumber => Response::SerialNumber([data[0],data[1],data[2],data[3],data[4],data[5]]),
-                    Request::ManufactureDate => Response::ManufactureDate{
-                        trans: TransmissionState::from_u8(data[0]).unwrap(),
-                        global: GlobalState::from_u8(data[1]).unwrap(),
-                        week: [data[2],data[3]],
-                        year: [data[4],data[5]],
-                    },
-                    Request::CumulativeEnergy(duration) => Response::CumulativeEnergy{
-                        trans: TransmissionState::from_u8(data[0]).unwrap(),
-                        global: GlobalState::from_u8(data[1]).unwrap(),
-                        value: BigEndian::read_u32(&data[2..]),
-                        duration: duration,
-                    }
-                }))
-            }else{
-                Err(io::Error::new(io::ErrorKind::Other,"Got response without request"))
-            }
+        Command::encode(self,addr)
+    }
 
+    /// Parses a request frame, returning the address it was sent to along with the request
+    pub fn decode(bytes: &[u8]) -> io::Result<(u8,Request)>
+    {
+        let frame = Frame::from_bytes(bytes)?;
+        Ok((frame.address,Request::from_frame(&frame)))
+    }
+
+    /// Interprets a request frame
+    ///
+    /// Frames which aren't exactly what this crate would send for one of its commands become
+    /// `Request::Raw`, so no bytes are lost. A `Raw` request for a known command doesn't survive
+    /// a roundtrip as `Raw`, but does encode to the same frame.
+    pub fn from_frame(frame: &Frame) -> Request
+    {
+        let raw = Request::Raw{opcode: frame.opcode, payload: frame.payload};
+        let known = match frame.opcode{
+            50 => Request::State,
+            52 => Request::PartNumber,
+            58 => Request::Version,
+            59 => match MeasurementType::try_from(frame.payload[0]) {
+                Ok(type_) => Request::Measure{type_, global: frame.payload[1] != 0},
+                Err(_) => return raw,
+            },
+            63 => Request::SerialNumber,
+            65 => Request::ManufactureDate,
+            78 => match CumulativeDuration::try_from(frame.payload[0]) {
+                Ok(duration) => Request::CumulativeEnergy(duration),
+                Err(_) => return raw,
+            },
+            _ => return raw,
+        };
+        if known.args() == frame.payload {
+            known
         }else{
-            Ok(None)
+            raw
         }
-    }    
+    }
 }
 
-impl Encoder for AuroraCodec{
-    type Item = (u8,Request);
-    type Error = std::io::Error;
-    fn encode(&mut self, (addr,msg): Self::Item, buf: &mut BytesMut)-> Result<(),Self::Error>
+impl Response{
+    /// The 6 data bytes of the response frame carrying this response
+    ///
+    /// Unlike `Request::encode` this isn't a whole frame, pass it to `encode_response` for that.
+    pub fn data(&self) -> [u8;6]
     {
-        let mut encoded:[u8;10] = [0;10];
-        encoded[0] = addr;
-        let crc = {
-            let data = &mut encoded[0..8];
-            match msg{
-                Request::State => {
-                    data[1] = 50;
-                }
-                Request::PartNumber => {
-                    data[1] = 52;
-                }
-                Request::Version => {
-                    data[1] = 58;
-                }
-                Request::Measure{type_,global} =>{
-                    data[1] = 59;
-                    data[2] = type_ as u8;
-                    data[3] = if global {1}else{0};
-                }
-                Request::SerialNumber => {
-                    data[1] = 63;
-                },
-                Request::ManufactureDate => {
-                    data[1] = 65;
-                },
-                Request::CumulativeEnergy(ref duration) => {
-                    data[1] = 78;
-                    data[2] = *duration as u8;
-                }
+        let mut data = [0;6];
+        match *self{
+            Response::State{trans,global,inverter,dc1,dc2,alarm} => {
+                data = [trans as u8,global as u8,inverter as u8,dc1 as u8,dc2 as u8,alarm];
             }
-            State::<AuroraCrc>::calculate(data)
-        };
-        encoded[8] = lo(crc);
-        encoded[9] = hi(crc);
-        self.last_request = Some(msg);
-        buf.put_slice(&encoded);
-        Ok(())
+            Response::PartNumber(part) => data = part,
+            Response::Version{trans,global,par1,par2,par3,par4} => {
+                data = [trans as u8,global as u8,par1,par2,par3,par4];
+            }
+            Response::Measure{trans,global,val,..} => {
+                data[0] = trans as u8;
+                data[1] = global as u8;
+                BigEndian::write_f32(&mut data[2..],val);
+            }
+            Response::SerialNumber(serial) => data = serial,
+            Response::ManufactureDate{trans,global,week,year} => {
+                data = [trans as u8,global as u8,week[0],week[1],year[0],year[1]];
+            }
+            Response::CumulativeEnergy{trans,global,value,..} => {
+                data[0] = trans as u8;
+                data[1] = global as u8;
+                BigEndian::write_u32(&mut data[2..],value);
+            }
+            Response::Raw(raw) => data = raw,
+        }
+        data
     }
 
+    /// Interprets the data bytes of a response frame as the response to `request`
+    ///
+    /// Fails if the frame contains a state code that isn't known
+    pub fn decode(request: &Request, data: [u8;6]) -> io::Result<Response>
+    {
+        Ok(match *request {
+            Request::State => Response::State{
+                trans: code(data[0])?,
+                global: code(data[1])?,
+                inverter: code(data[2])?,
+                dc1: code(data[3])?,
+                dc2: code(data[4])?,
+                alarm: data[5]
+            },
+            Request::PartNumber => Response::PartNumber([data[0],data[1],data[2],data[3],data[4],data[5]]),
+            Request::Version => Response::Version{
+                trans: code(data[0])?,
+                global: code(data[1])?,
+                par1: data[2],
+                par2: data[3],
+                par3: data[4],
+                par4: data[5]
+            },
+            Request::Measure{type_,..} => Response::Measure{
+                trans: code(data[0])?,
+                global: code(data[1])?,
+                val: BigEndian::read_f32(&data[2..]),
+                type_
+            },
+            Request::SerialNumber => Response::SerialNumber([data[0],data[1],data[2],data[3],data[4],data[5]]),
+            Request::ManufactureDate => Response::ManufactureDate{
+                trans: code(data[0])?,
+                global: code(data[1])?,
+                week: [data[2],data[3]],
+                year: [data[4],data[5]],
+            },
+            Request::CumulativeEnergy(duration) => Response::CumulativeEnergy{
+                trans: code(data[0])?,
+                global: code(data[1])?,
+                value: BigEndian::read_u32(&data[2..]),
+                duration,
+            },
+            Request::Raw{..} => Response::Raw(data),
+        })
+    }
 }
 
-pub struct AuroraProto;
+fn code<T: TryFrom<u8,Error = UnknownCode>>(val: u8) -> io::Result<T>
+{
+    Ok(T::try_from(val)?)
+}
 
-impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for AuroraProto{
-    type Request = (u8,Request);
-    type Response = Response;
-    type Transport = Framed<T, AuroraCodec>;
-    type BindTransport = StdResult<Self::Transport, io::Error>;
-    fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(AuroraCodec{last_request:None}))
+/// Decodes an arbitrary response frame, intended as the entry point for fuzzing
+///
+/// Checks the length and crc of `packet`, and if the `request` it answers is known interprets
+/// the data as the response to it. Never panics, whatever the input.
+pub fn decode_any_frame(packet: &[u8], request: Option<&Request>) -> io::Result<Option<Response>>
+{
+    let data = decode_frame(packet)?;
+    match request {
+        Some(request) => Response::decode(request,data).map(Some),
+        None => Ok(None),
     }
 }
 
-type AuroraCrc = crc16::X_25;
-
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
     }
+
+    #[test]
+    fn unknown_state_codes_are_errors() {
+        let response = encode_response([0,200,0,0,0,0]);
+        assert!(decode_any_frame(&response,None).unwrap().is_none());
+        let err = decode_any_frame(&response,Some(&Request::State)).unwrap_err();
+        assert_eq!(err.kind(),io::ErrorKind::InvalidData);
+        assert!(decode_any_frame(&response,Some(&Request::PartNumber)).unwrap().is_some());
+    }
+
+    #[test]
+    fn codes_convert_both_ways() {
+        assert_eq!(GlobalState::try_from(6),Ok(GlobalState::Run));
+        assert_eq!(u8::from(GlobalState::Run),6);
+        assert_eq!(CumulativeDuration::try_from(2),Err(UnknownCode{kind: "CumulativeDuration", code: 2}));
+        assert_eq!(io::Error::from(UnknownCode{kind: "GlobalState", code: 200}).to_string(),"Unknown GlobalState code 200");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn montly_is_an_alias_for_monthly() {
+        assert_eq!(CumulativeDuration::Montly,CumulativeDuration::Monthly);
+        let mut totals = ::std::collections::HashMap::new();
+        totals.insert(CumulativeDuration::Monthly,1);
+        assert_eq!(totals.get(&CumulativeDuration::Montly),Some(&1));
+    }
+
+    #[test]
+    fn requests_and_responses_roundtrip() {
+        let req = Request::Measure{type_: MeasurementType::GridPower, global: true};
+        assert_eq!(Request::decode(&req.encode(2)).unwrap(),(2,req.clone()));
+        let res = Response::Measure{
+            trans: TransmissionState::Ok,
+            global: GlobalState::Run,
+            val: 1234.5,
+            type_: MeasurementType::GridPower,
+        };
+        assert_eq!(Response::decode(&req,res.data()).unwrap(),res);
+    }
+
+    #[test]
+    fn raw_requests_pass_bytes_through() {
+        let req = Request::Raw{opcode: 70, payload: [1,2,3,4,5,6]};
+        assert_eq!(req.encode(2),encode_frame(2,70,[1,2,3,4,5,6]));
+        match Response::decode(&req,[0,6,1,2,3,4]).unwrap() {
+            Response::Raw(data) => assert_eq!(data,[0,6,1,2,3,4]),
+            other => panic!("unexpected response {:?}",other),
+        }
+    }
 }
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use Frame;
+
+/// Callbacks for protocol events, for collecting metrics or debugging without changing the codec
+///
+/// Every method does nothing by default. Attach one to the codec with `AuroraProto::observed` for
+/// the frame level events, and to a `Dispatcher` with `ClientHandle::with_observer` for the outcome
+/// of each request. Nothing in this crate reconnects, so `reconnect` is there for the
+/// application's connection handling to report through, so that all the events can end up in one
+/// place.
+pub trait Observer{
+    /// A request frame was written to the transport
+    fn frame_sent(&self, _frame: &Frame) {}
+
+    /// A response frame with a valid crc was read, `packet` includes the crc bytes
+    fn frame_received(&self, _packet: &[u8]) {}
+
+    /// A response frame was read, but its crc didn't match
+    fn crc_error(&self, _packet: &[u8]) {}
+
+    /// A request to the inverter at `address` was answered after `latency`
+    fn response_received(&self, _address: u8, _latency: Duration) {}
+
+    /// No response arrived in time for a request to the inverter at `address`
+    fn timeout(&self, _address: u8) {}
+
+    /// The connection was re-established
+    fn reconnect(&self) {}
+}
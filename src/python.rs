@@ -0,0 +1,218 @@
+//! Python bindings for the frame layer, the state code tables and a blocking client, built as the
+//! `pyaurora` module
+//!
+//! Build with `maturin build --features python`, see `pyproject.toml`.
+
+// triggered by the code pyo3's #[pyfunction] expands to for PyResult returns
+#![allow(clippy::useless_conversion)]
+
+use std::convert::TryFrom;
+use std::io::{self,Read,Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+use {encode_frame,decode_frame,Command,CumulativeDuration,RESPONSE_LEN};
+use {TransmissionState,GlobalState,InverterState,DcDcState,MeasurementType};
+use commands::{GetCumulativeEnergy,GetSerialNumber,GetState,Measure,MeasureGlobal};
+
+/// Builds the 10 byte request frame for `opcode` with the 6 argument bytes `args`
+#[pyfunction]
+#[pyo3(name = "encode_frame")]
+fn py_encode_frame(address: u8, opcode: u8, args: [u8;6]) -> Vec<u8>
+{
+    encode_frame(address,opcode,args).to_vec()
+}
+
+/// Checks the crc of an 8 byte response frame, and returns its 6 data bytes
+#[pyfunction]
+#[pyo3(name = "decode_frame")]
+fn py_decode_frame(frame: &[u8]) -> PyResult<Vec<u8>>
+{
+    decode_frame(frame)
+        .map(|data| data.to_vec())
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Name of a transmission state code, or None if the code is unknown
+#[pyfunction]
+fn transmission_state_name(code: u8) -> Option<&'static str>
+{
+    TransmissionState::try_from(code).ok().map(|s| s.name())
+}
+
+/// Name of a global state code, or None if the code is unknown
+#[pyfunction]
+fn global_state_name(code: u8) -> Option<&'static str>
+{
+    GlobalState::try_from(code).ok().map(|s| s.name())
+}
+
+/// Name of an inverter state code, or None if the code is unknown
+#[pyfunction]
+fn inverter_state_name(code: u8) -> Option<&'static str>
+{
+    InverterState::try_from(code).ok().map(|s| s.name())
+}
+
+/// Name of a dc/dc converter state code, or None if the code is unknown
+#[pyfunction]
+fn dcdc_state_name(code: u8) -> Option<&'static str>
+{
+    DcDcState::try_from(code).ok().map(|s| s.name())
+}
+
+/// Name of a measurement type code, or None if the code is unknown
+#[pyfunction]
+fn measurement_type_name(code: u8) -> Option<&'static str>
+{
+    MeasurementType::try_from(code).ok().map(|s| s.name())
+}
+
+/// Sends `cmd` to the inverter at `address` and waits for its response
+fn send<C: Command>(stream: &mut TcpStream, address: u8, cmd: &C) -> io::Result<C::Output>
+{
+    // drop anything left over from a request which timed out, so it isn't taken as this response
+    stream.set_nonblocking(true)?;
+    let mut stale = [0;64];
+    let drained = loop {
+        match stream.read(&mut stale) {
+            Ok(0) => break Err(io::Error::new(io::ErrorKind::UnexpectedEof,"Connection closed")),
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    drained?;
+    stream.write_all(&cmd.encode(address))?;
+    let mut response = [0;RESPONSE_LEN];
+    stream.read_exact(&mut response).map_err(|e| match e.kind() {
+        io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut,"No response from the inverter"),
+        _ => e,
+    })?;
+    cmd.parse(decode_frame(&response)?)
+}
+
+fn code<T: TryFrom<u8>>(code: u8) -> PyResult<T>
+    where T::Error: ToString
+{
+    T::try_from(code).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// A blocking client for inverters behind a TCP serial bridge, such as ser2net
+///
+/// Requests wait at most `timeout` seconds for a response, raising TimeoutError after that.
+#[pyclass]
+struct Client{
+    stream: TcpStream,
+}
+
+impl Client{
+    /// Sends `cmd`, letting other Python threads run while waiting for the inverter
+    fn send<C>(&mut self, py: Python<'_>, address: u8, cmd: C) -> io::Result<C::Output>
+        where C: Command + Send, C::Output: Send
+    {
+        let stream = &mut self.stream;
+        py.allow_threads(move || send(stream,address,&cmd))
+    }
+}
+
+#[pymethods]
+impl Client{
+    /// Connects to the bridge at `address`, given as "host:port"
+    #[new]
+    #[pyo3(signature = (address, timeout = 1.0))]
+    fn new(address: &str, timeout: f64) -> PyResult<Client>
+    {
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(Client{stream})
+    }
+
+    /// The names of the transmission, global, inverter and both dc/dc converter states, and the
+    /// alarm code
+    fn state(&mut self, py: Python<'_>, address: u8) -> PyResult<(&'static str,&'static str,&'static str,&'static str,&'static str,u8)>
+    {
+        let state = self.send(py,address,GetState)?;
+        Ok((state.trans.name(),state.global.name(),state.inverter.name(),state.dc1.name(),state.dc2.name(),state.alarm))
+    }
+
+    /// Reads the measurement with type code `type_`, summed over the whole inverter if `global`
+    #[pyo3(signature = (address, type_, global = false))]
+    fn measure(&mut self, py: Python<'_>, address: u8, type_: u8, global: bool) -> PyResult<f32>
+    {
+        let type_ = code(type_)?;
+        Ok(if global {
+            self.send(py,address,MeasureGlobal(type_))?
+        }else{
+            self.send(py,address,Measure(type_))?
+        })
+    }
+
+    fn serial_number(&mut self, py: Python<'_>, address: u8) -> PyResult<String>
+    {
+        let serial = self.send(py,address,GetSerialNumber)?;
+        Ok(String::from_utf8_lossy(&serial).into_owned())
+    }
+
+    /// The energy produced over the period with code `period`, in Wh
+    fn cumulative_energy(&mut self, py: Python<'_>, address: u8, period: u8) -> PyResult<u32>
+    {
+        let period: CumulativeDuration = code(period)?;
+        Ok(self.send(py,address,GetCumulativeEnergy(period))?)
+    }
+}
+
+#[pymodule]
+fn pyaurora(m: &Bound<'_, PyModule>) -> PyResult<()>
+{
+    m.add_function(wrap_pyfunction!(python::py_encode_frame, m)?)?;
+    m.add_function(wrap_pyfunction!(python::py_decode_frame, m)?)?;
+    m.add_function(wrap_pyfunction!(python::transmission_state_name, m)?)?;
+    m.add_function(wrap_pyfunction!(python::global_state_name, m)?)?;
+    m.add_function(wrap_pyfunction!(python::inverter_state_name, m)?)?;
+    m.add_function(wrap_pyfunction!(python::dcdc_state_name, m)?)?;
+    m.add_function(wrap_pyfunction!(python::measurement_type_name, m)?)?;
+    m.add_class::<Client>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use byteorder::{BigEndian,ByteOrder};
+    use {encode_response,REQUEST_LEN};
+
+    #[test]
+    fn send_skips_stale_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let inverter = thread::spawn(move || {
+            let (mut stream,_) = listener.accept().unwrap();
+            let mut request = [0;REQUEST_LEN];
+            // leave the first request unanswered until the client has given up on it
+            stream.read_exact(&mut request).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stream.write_all(&encode_response([0,6,0,0,0,0])).unwrap();
+            stream.read_exact(&mut request).unwrap();
+            let mut data = [0,6,0,0,0,0];
+            BigEndian::write_f32(&mut data[2..],1234.5);
+            stream.write_all(&encode_response(data)).unwrap();
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        let cmd = Measure(MeasurementType::GridPower);
+        assert_eq!(send(&mut stream,2,&cmd).unwrap_err().kind(),io::ErrorKind::TimedOut);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(send(&mut stream,2,&cmd).unwrap(),1234.5);
+        inverter.join().unwrap();
+    }
+}
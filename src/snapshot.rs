@@ -0,0 +1,402 @@
+//! Helpers gathering groups of related measurements in one call
+
+use std::io;
+use std::mem;
+
+use futures::{Async,Future,Poll};
+use tokio_service::Service;
+
+use {AddressedClient,MeasurementType,Request,Response,SendCommand};
+use commands::{Measure,Refused};
+
+/// Future returned by `AddressedClient::measure_all`
+///
+/// Sends one measurement request at a time, as the bus is half duplex, and gives up at the first
+/// error other than the inverter refusing a measurement.
+pub struct MeasureAll<S: Service>{
+    client: AddressedClient<S>,
+    types: Vec<MeasurementType>,
+    results: Vec<io::Result<f32>>,
+    in_flight: Option<SendCommand<S::Future,Measure>>,
+}
+
+impl<S> Future for MeasureAll<S>
+    where S: Service<Request = (u8,Request), Response = Response, Error = io::Error>
+{
+    type Item = Vec<io::Result<f32>>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Vec<io::Result<f32>>,io::Error>
+    {
+        loop {
+            if let Some(mut in_flight) = self.in_flight.take() {
+                match in_flight.poll() {
+                    Ok(Async::NotReady) => {
+                        self.in_flight = Some(in_flight);
+                        return Ok(Async::NotReady);
+                    }
+                    Ok(Async::Ready(value)) => self.results.push(Ok(value)),
+                    Err(e) => {
+                        if Refused::from_io(&e).is_none() {
+                            return Err(e);
+                        }
+                        self.results.push(Err(e));
+                    }
+                }
+            }
+            match self.types.get(self.results.len()) {
+                Some(&type_) => self.in_flight = Some(self.client.send(Measure(type_))),
+                None => return Ok(Async::Ready(mem::take(&mut self.results))),
+            }
+        }
+    }
+}
+
+/// The readings for one input string
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct StringReading{
+    /// V
+    pub voltage: f32,
+    /// A
+    pub current: f32,
+    /// W
+    pub power: f32,
+}
+
+/// The readings for both inputs of a dual MPPT inverter
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct StringSnapshot{
+    pub input1: StringReading,
+    pub input2: StringReading,
+}
+
+impl StringSnapshot{
+    /// The difference in power between the two strings, as a fraction of the stronger one
+    ///
+    /// Identical strings should stay close to 0, a persistent gap points at shading or a failed
+    /// panel or connector.
+    pub fn imbalance(&self) -> f32
+    {
+        let max = self.input1.power.max(self.input2.power);
+        if max <= 0.0 {
+            0.0
+        }else{
+            (self.input1.power - self.input2.power).abs() / max
+        }
+    }
+}
+
+/// Isolation resistance and leakage current readings
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Insulation{
+    /// Riso, MΩ
+    pub isolation_resistance: f32,
+    /// Ileak of the dc/dc converter, A
+    pub leakage_dc: f32,
+    /// Ileak of the inverter, A
+    pub leakage_inverter: f32,
+}
+
+/// The levels at which an inverter disconnects for an insulation fault
+///
+/// These depend on the model and the grid standard it's configured for, so take them from its
+/// manual.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct InsulationLimits{
+    /// The lowest allowed isolation resistance, MΩ
+    pub min_isolation_resistance: f32,
+    /// The highest allowed leakage current, A
+    pub max_leakage: f32,
+    /// How close to a limit counts as near it, as a fraction of the limit, e.g. 0.2
+    pub margin: f32,
+}
+
+impl Insulation{
+    /// Whether any reading is within `limits.margin` of tripping
+    ///
+    /// Riso falls on damp mornings, so this flags a "Riso Low" fault before it happens.
+    pub fn near_trip(&self, limits: &InsulationLimits) -> bool
+    {
+        let max_leakage = limits.max_leakage * (1.0 - limits.margin);
+        self.isolation_resistance <= limits.min_isolation_resistance * (1.0 + limits.margin)
+            || self.leakage_dc >= max_leakage
+            || self.leakage_inverter >= max_leakage
+    }
+}
+
+/// Temperatures, °C, and fan speeds, rpm
+///
+/// Most models only have some of the sensors, the others are `None`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Thermals{
+    pub inverter: f32,
+    pub booster: f32,
+    pub supervisor: Option<f32>,
+    pub alim: Option<f32>,
+    pub heat_sink: Option<f32>,
+    pub temps: [Option<f32>;3],
+    pub fan_speeds: [Option<f32>;5],
+}
+
+/// Grid and bulk capacitor voltages, V, and grid frequency, Hz
+///
+/// Single bulk models don't report the split bulk voltages, so those are `None`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct GridQuality{
+    pub grid_voltage: f32,
+    pub average_grid_voltage: f32,
+    pub frequency: f32,
+    pub vbulk: f32,
+    pub vbulk_pos: Option<f32>,
+    pub vbulk_neg: Option<f32>,
+}
+
+/// The generator side readings of an Aurora wind inverter
+///
+/// Wind models take the rectified generator output on input 1 rather than a panel string. The
+/// protocol has no generator speed measurement, derive it from the frequency and the number of
+/// pole pairs.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Wind{
+    /// Hz
+    pub generator_frequency: f32,
+    pub input: StringReading,
+    /// W
+    pub grid_power: f32,
+}
+
+/// All of the measurements, or the first error
+fn all_ok(results: Vec<io::Result<f32>>) -> io::Result<Vec<f32>>
+{
+    results.into_iter().collect()
+}
+
+/// A measurement of a sensor the model may not have, `None` if the inverter says it doesn't
+fn optional(result: io::Result<f32>) -> io::Result<Option<f32>>
+{
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => match Refused::from_io(&e) {
+            Some(ref refused) if refused.is_unsupported() => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+impl<S> AddressedClient<S>
+    where S: Service<Request = (u8,Request), Response = Response, Error = io::Error> + Clone
+{
+    /// Measures each of `types` in turn, resolving to one result per type
+    ///
+    /// A measurement the inverter refuses doesn't stop the rest being taken, its result is the
+    /// `Refused` error. Any other error, such as a timeout, fails the whole future without sending
+    /// the remaining requests.
+    pub fn measure_all(&self, types: &[MeasurementType]) -> MeasureAll<S>
+    {
+        MeasureAll{
+            client: self.clone(),
+            types: types.to_vec(),
+            results: Vec::with_capacity(types.len()),
+            in_flight: None,
+        }
+    }
+
+    /// Measures the voltage, current and power of both input strings
+    pub fn strings(&self) -> impl Future<Item = StringSnapshot, Error = io::Error>
+    {
+        self.measure_all(MeasurementType::STRINGS).and_then(|results| {
+            let v = all_ok(results)?;
+            Ok(StringSnapshot{
+                input1: StringReading{voltage: v[0], current: v[1], power: v[2]},
+                input2: StringReading{voltage: v[3], current: v[4], power: v[5]},
+            })
+        })
+    }
+
+    /// Measures the isolation resistance and both leakage currents
+    pub fn insulation(&self) -> impl Future<Item = Insulation, Error = io::Error>
+    {
+        self.measure_all(MeasurementType::INSULATION).and_then(|results| {
+            let v = all_ok(results)?;
+            Ok(Insulation{isolation_resistance: v[0], leakage_dc: v[1], leakage_inverter: v[2]})
+        })
+    }
+
+    /// Measures every temperature and fan speed
+    ///
+    /// Fails if the inverter or booster temperature can't be read, or on any error other than the
+    /// inverter saying it doesn't have one of the other sensors.
+    pub fn thermals(&self) -> impl Future<Item = Thermals, Error = io::Error>
+    {
+        self.measure_all(MeasurementType::THERMALS).and_then(|results| {
+            let mut results = results.into_iter();
+            let mut next = || results.next().unwrap();
+            Ok(Thermals{
+                inverter: next()?,
+                booster: next()?,
+                supervisor: optional(next())?,
+                alim: optional(next())?,
+                heat_sink: optional(next())?,
+                temps: [optional(next())?,optional(next())?,optional(next())?],
+                fan_speeds: [
+                    optional(next())?,
+                    optional(next())?,
+                    optional(next())?,
+                    optional(next())?,
+                    optional(next())?,
+                ],
+            })
+        })
+    }
+
+    /// Measures the grid voltage and frequency along with the bulk voltages, for diagnosing grid
+    /// over voltage disconnects
+    ///
+    /// The protocol has no code for the peak grid voltage, so it isn't included.
+    /// The split bulk voltages are `None` when the inverter says it doesn't have them, any other
+    /// error fails the snapshot.
+    pub fn grid_quality(&self) -> impl Future<Item = GridQuality, Error = io::Error>
+    {
+        self.measure_all(MeasurementType::GRID_QUALITY).and_then(|results| {
+            let mut results = results.into_iter();
+            let mut next = || results.next().unwrap();
+            Ok(GridQuality{
+                grid_voltage: next()?,
+                average_grid_voltage: next()?,
+                frequency: next()?,
+                vbulk: next()?,
+                vbulk_pos: optional(next())?,
+                vbulk_neg: optional(next())?,
+            })
+        })
+    }
+
+    /// Measures the generator frequency and input of a wind inverter
+    pub fn wind(&self) -> impl Future<Item = Wind, Error = io::Error>
+    {
+        self.measure_all(MeasurementType::WIND).and_then(|results| {
+            let v = all_ok(results)?;
+            Ok(Wind{
+                generator_frequency: v[0],
+                input: StringReading{voltage: v[1], current: v[2], power: v[3]},
+                grid_power: v[4],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use fake::{measurement,service_fn,ServiceFn};
+    use {ForAddress,TransmissionState};
+
+    /// An inverter reporting each measurement as its own type code, refusing those in `refused` and
+    /// never answering those in `silent`
+    fn inverter(refused: &'static [MeasurementType], silent: &'static [MeasurementType])
+        -> AddressedClient<ServiceFn<impl Fn(u8,Request) -> io::Result<Response> + Clone>>
+    {
+        service_fn(move |_,req| {
+            let code = match req {
+                Request::Measure{type_,..} => type_ as u8,
+                other => panic!("unexpected request {:?}",other),
+            };
+            if silent.iter().any(|&t| t as u8 == code) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut,"no response"));
+            }
+            let state = if refused.iter().any(|&t| t as u8 == code) {
+                TransmissionState::CommandNotImplemented
+            }else{
+                TransmissionState::Ok
+            };
+            Ok(measurement(state,code as f32))
+        }).for_address(2)
+    }
+
+    #[test]
+    fn measure_all_keeps_going_after_a_refusal() {
+        let client = inverter(&[MeasurementType::Pin1],&[]);
+        let results = client.measure_all(&[MeasurementType::Pin1,MeasurementType::Pin2]).wait().unwrap();
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap(),&9.0);
+    }
+
+    #[test]
+    fn measure_all_stops_at_a_transport_error() {
+        let sent = Rc::new(Cell::new(0));
+        let counter = sent.clone();
+        let client = service_fn(move |_,_| {
+            counter.set(counter.get() + 1);
+            Err::<Response,_>(io::Error::new(io::ErrorKind::TimedOut,"no response"))
+        }).for_address(2);
+        let err = client.measure_all(&[MeasurementType::Pin1,MeasurementType::Pin2]).wait().unwrap_err();
+        assert_eq!(err.kind(),io::ErrorKind::TimedOut);
+        assert_eq!(sent.get(),1);
+    }
+
+    #[test]
+    fn strings_snapshot() {
+        let client = inverter(&[],&[]);
+        let strings = client.strings().wait().unwrap();
+        assert_eq!(strings.input1,StringReading{voltage: 23.0, current: 25.0, power: 8.0});
+        assert_eq!(strings.input2,StringReading{voltage: 26.0, current: 27.0, power: 9.0});
+        assert!((strings.imbalance() - 1.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn insulation_near_trip() {
+        let client = inverter(&[],&[]);
+        let insulation = client.insulation().wait().unwrap();
+        assert_eq!(insulation,Insulation{isolation_resistance: 30.0, leakage_dc: 6.0, leakage_inverter: 7.0});
+        let limits = InsulationLimits{min_isolation_resistance: 20.0, max_leakage: 10.0, margin: 0.2};
+        assert!(!insulation.near_trip(&limits));
+        assert!(Insulation{isolation_resistance: 23.0, ..insulation}.near_trip(&limits));
+        assert!(Insulation{leakage_inverter: 8.5, ..insulation}.near_trip(&limits));
+        assert_eq!(MeasurementType::IsolationResistance.unit(),"MΩ");
+    }
+
+    #[test]
+    fn thermals_skip_missing_sensors() {
+        use MeasurementType::*;
+        let client = inverter(&[Temp2,Temp3,FanSpeed2,FanSpeed3,FanSpeed4,FanSpeed5],&[]);
+        let thermals = client.thermals().wait().unwrap();
+        assert_eq!((thermals.inverter,thermals.heat_sink),(21.0,Some(49.0)));
+        assert_eq!(thermals.temps,[Some(50.0),None,None]);
+        assert_eq!(thermals.fan_speeds,[Some(53.0),None,None,None,None]);
+
+        let client = inverter(&[BoosterTemperature],&[]);
+        assert!(client.thermals().wait().is_err());
+
+        let client = inverter(&[],&[HeatSinkTemp]);
+        assert_eq!(client.thermals().wait().unwrap_err().kind(),io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn grid_quality_without_split_bulk() {
+        let client = inverter(&[MeasurementType::VbulkPos,MeasurementType::VbulkNeg],&[]);
+        assert_eq!(client.grid_quality().wait().unwrap(),GridQuality{
+            grid_voltage: 1.0,
+            average_grid_voltage: 32.0,
+            frequency: 4.0,
+            vbulk: 5.0,
+            vbulk_pos: None,
+            vbulk_neg: None,
+        });
+    }
+
+    #[test]
+    fn grid_quality_fails_on_a_timeout() {
+        let client = inverter(&[],&[MeasurementType::VbulkNeg]);
+        assert_eq!(client.grid_quality().wait().unwrap_err().kind(),io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn wind_snapshot() {
+        let client = inverter(&[],&[]);
+        let wind = client.wind().wait().unwrap();
+        assert_eq!((wind.generator_frequency,wind.grid_power),(37.0,3.0));
+        assert_eq!(wind.input,StringReading{voltage: 23.0, current: 25.0, power: 8.0});
+    }
+}
@@ -0,0 +1,118 @@
+use std::io;
+
+use bytes::BytesMut;
+use tokio_codec::Decoder;
+
+use {Request,Response,decode_frame,REQUEST_LEN,RESPONSE_LEN};
+
+/// A request seen on the bus, and the response to it if there was one
+#[derive(Clone,Debug,PartialEq)]
+pub struct Exchange{
+    pub address: u8,
+    pub request: Request,
+    /// `None` if the next request was sent without the inverter answering
+    pub response: Option<Response>,
+}
+
+/// Decodes the traffic of a bus polled by another master, such as the original Aurora datalogger
+///
+/// Reads both the request frames and their responses, pairing them up into `Exchange`s, so
+/// `FramedRead::new(port, SnifferCodec::new())` is a stream of everything said on the bus. It
+/// never writes, leaving the existing monitoring undisturbed. Bytes which don't form a frame with
+/// a valid crc are skipped until it finds one again.
+#[derive(Debug,Default)]
+pub struct SnifferCodec{
+    pending: Option<(u8,Request)>,
+}
+
+impl SnifferCodec{
+    pub fn new() -> SnifferCodec
+    {
+        SnifferCodec::default()
+    }
+}
+
+impl Decoder for SnifferCodec{
+    type Item = Exchange;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Exchange>>
+    {
+        loop {
+            if let Some((address,request)) = self.pending.take() {
+                if buf.len() < RESPONSE_LEN {
+                    self.pending = Some((address,request));
+                    return Ok(None);
+                }
+                if let Ok(data) = decode_frame(&buf[..RESPONSE_LEN]) {
+                    let _ = buf.split_to(RESPONSE_LEN);
+                    // an inverter answering with a code we don't know shouldn't stop the stream
+                    let response = Response::decode(&request,data).unwrap_or(Response::Raw(data));
+                    return Ok(Some(Exchange{address,request,response:Some(response)}));
+                }
+                if buf.len() < REQUEST_LEN {
+                    self.pending = Some((address,request));
+                    return Ok(None);
+                }
+                // no answer, the master has moved on to its next request
+                if Request::decode(&buf[..REQUEST_LEN]).is_ok() {
+                    return Ok(Some(Exchange{address,request,response:None}));
+                }
+                let _ = buf.split_to(1);
+                self.pending = Some((address,request));
+                continue;
+            }
+            if buf.len() < REQUEST_LEN {
+                return Ok(None);
+            }
+            match Request::decode(&buf[..REQUEST_LEN]) {
+                Ok(req) => {
+                    let _ = buf.split_to(REQUEST_LEN);
+                    self.pending = Some(req);
+                }
+                Err(_) => {
+                    let _ = buf.split_to(1);
+                }
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Exchange>>
+    {
+        if let Some(exchange) = self.decode(buf)? {
+            return Ok(Some(exchange));
+        }
+        buf.clear();
+        Ok(self.pending.take().map(|(address,request)| Exchange{address,request,response:None}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode_response;
+
+    #[test]
+    fn pairs_requests_with_responses() {
+        let mut codec = SnifferCodec::new();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0xff,0x00]);
+        buf.extend_from_slice(&Request::State.encode(2));
+        buf.extend_from_slice(&encode_response([0,6,2,0,0,0])[..4]);
+        assert_eq!(codec.decode(&mut buf).unwrap(),None);
+        buf.extend_from_slice(&encode_response([0,6,2,0,0,0])[4..]);
+        buf.extend_from_slice(&Request::SerialNumber.encode(3));
+        buf.extend_from_slice(&Request::PartNumber.encode(2));
+
+        let exchange = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!((exchange.address,exchange.request),(2,Request::State));
+        match exchange.response {
+            Some(Response::State{..}) => {}
+            other => panic!("unexpected response {:?}",other),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(),Some(Exchange{address: 3, request: Request::SerialNumber, response: None}));
+        assert_eq!(codec.decode(&mut buf).unwrap(),None);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(),Some(Exchange{address: 2, request: Request::PartNumber, response: None}));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(),None);
+    }
+}
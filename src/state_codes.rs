@@ -1,6 +1,86 @@
-enum_from_primitive! {
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// A code which isn't one of the values known for the type it was being converted to
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct UnknownCode{
+    /// The name of the type the code was being converted to
+    pub kind: &'static str,
+    pub code: u8,
+}
+
+impl fmt::Display for UnknownCode{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f,"Unknown {} code {}",self.kind,self.code)
+    }
+}
+
+impl Error for UnknownCode{}
+
+impl From<UnknownCode> for io::Error{
+    fn from(err: UnknownCode) -> io::Error
+    {
+        io::Error::new(io::ErrorKind::InvalidData,err)
+    }
+}
+
+macro_rules! state_enum {
+    ($(#[$meta:meta])* pub enum $name:ident { $($variant:ident = $val:expr),* $(,)* }) => {
+        $(#[$meta])*
+        pub enum $name{
+            $($variant = $val),*
+        }
+
+        impl TryFrom<u8> for $name{
+            type Error = UnknownCode;
+
+            fn try_from(code: u8) -> Result<$name,UnknownCode>
+            {
+                $(if code == $val {
+                    return Ok($name::$variant);
+                })*
+                Err(UnknownCode{kind: stringify!($name), code})
+            }
+        }
+
+        impl From<$name> for u8{
+            fn from(val: $name) -> u8
+            {
+                val as u8
+            }
+        }
+
+        impl $name{
+            /// Every known code, in declaration order
+            pub const VARIANTS: &'static [$name] = &[$($name::$variant),*];
+
+            /// The name of this code, as used for the enum variant
+            pub fn name(&self) -> &'static str
+            {
+                match *self{
+                    $($name::$variant => stringify!($variant)),*
+                }
+            }
+
+            /// The name of this code as a nul terminated string, for handing out over ffi
+            #[cfg(feature = "ffi")]
+            pub(crate) fn c_name(&self) -> &'static [u8]
+            {
+                match *self{
+                    $($name::$variant => concat!(stringify!($variant), "\0").as_bytes()),*
+                }
+            }
+        }
+    }
+}
+
+state_enum! {
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum MeasurementType{
     GridVoltage = 1,
     GridCurrent = 2,
@@ -56,11 +136,91 @@ pub enum MeasurementType{
 }
 }
 
+impl MeasurementType{
+    /// Voltage, current and power of input 1 then input 2, as read by `AddressedClient::strings`
+    pub const STRINGS: &'static [MeasurementType] = &[
+        MeasurementType::Input1Voltage,
+        MeasurementType::Input1Current,
+        MeasurementType::Pin1,
+        MeasurementType::Input2Voltage,
+        MeasurementType::Input2Current,
+        MeasurementType::Pin2,
+    ];
+
+    /// Isolation resistance and the dc/dc and inverter leakage currents, as read by
+    /// `AddressedClient::insulation`
+    pub const INSULATION: &'static [MeasurementType] = &[
+        MeasurementType::IsolationResistance,
+        MeasurementType::IleakDc,
+        MeasurementType::ILeakInverter,
+    ];
+
+    /// Every temperature then every fan speed, as read by `AddressedClient::thermals`
+    pub const THERMALS: &'static [MeasurementType] = &[
+        MeasurementType::InverterTemperature,
+        MeasurementType::BoosterTemperature,
+        MeasurementType::SupervisorTemp,
+        MeasurementType::AlimTemp,
+        MeasurementType::HeatSinkTemp,
+        MeasurementType::Temp1,
+        MeasurementType::Temp2,
+        MeasurementType::Temp3,
+        MeasurementType::FanSpeed1,
+        MeasurementType::FanSpeed2,
+        MeasurementType::FanSpeed3,
+        MeasurementType::FanSpeed4,
+        MeasurementType::FanSpeed5,
+    ];
+
+    /// Grid voltage, average grid voltage, frequency and the bulk voltages, as read by
+    /// `AddressedClient::grid_quality`
+    pub const GRID_QUALITY: &'static [MeasurementType] = &[
+        MeasurementType::GridVoltage,
+        MeasurementType::AverageGridVoltage,
+        MeasurementType::Frequency,
+        MeasurementType::Vbulk,
+        MeasurementType::VbulkPos,
+        MeasurementType::VbulkNeg,
+    ];
+
+    /// The measurements of an Aurora wind inverter, as read by `AddressedClient::wind`: generator
+    /// frequency, the rectified generator output on input 1, and grid power
+    pub const WIND: &'static [MeasurementType] = &[
+        MeasurementType::WindGeneratorFrequency,
+        MeasurementType::Input1Voltage,
+        MeasurementType::Input1Current,
+        MeasurementType::Pin1,
+        MeasurementType::GridPower,
+    ];
+
+    /// The unit the inverter reports this measurement in
+    pub fn unit(&self) -> &'static str
+    {
+        use self::MeasurementType::*;
+        match *self{
+            GridVoltage | Vbulk | Input1Voltage | Input2Voltage | GridVoltageDc | VbulkDc
+                | AverageGridVoltage | VbulkMid | GridVoltageNeutral | GridVoltageNeutralPhase
+                | VbulkPos | VbulkNeg | ReferenceRingBulk | VpanelMicro | GridVoltagePhaseR
+                | GridVoltagePhaseS | GridVoltagePhaseT => "V",
+            GridCurrent | IleakDc | ILeakInverter | Input1Current | Input2Current | GridCurrentPhaseR
+                | GridCurrentPhaseS | GridCurrentPhaseT => "A",
+            GridPower | Pin1 | Pin2 | PeakPower | PeakPowerToday | PowerSaturationLimit => "W",
+            Frequency | GridFrequencyDc | WindGeneratorFrequency | FrequencyPhaseR | FrequencyPhaseS
+                | FrequencyPhaseT => "Hz",
+            InverterTemperature | BoosterTemperature | SupervisorTemp | AlimTemp | HeatSinkTemp | Temp1
+                | Temp2 | Temp3 => "°C",
+            IsolationResistance => "MΩ",
+            FanSpeed1 | FanSpeed2 | FanSpeed3 | FanSpeed4 | FanSpeed5 => "rpm",
+        }
+    }
+}
+
 
 
-enum_from_primitive! {
+state_enum! {
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum TransmissionState{ 
     Ok = 0,
     CommandNotImplemented = 51,
@@ -74,9 +234,10 @@ pub enum TransmissionState{
 }
 }
 
-enum_from_primitive! {
+state_enum! {
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum GlobalState{ 
     SendingParameters=0,
     WaitSunOrGrid=1,
@@ -122,9 +283,10 @@ pub enum GlobalState{
 }
 }
 
-enum_from_primitive! {
+state_enum! {
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum DcDcState{
     DcDcOFF=0,
     RampStart=1,
@@ -149,9 +311,10 @@ pub enum DcDcState{
 }
 }
 
-enum_from_primitive! {
+state_enum! {
 #[repr(u8)]
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "test-support", derive(Arbitrary))]
 pub enum InverterState{
     StandBy=0,
     CheckingGrid=1,
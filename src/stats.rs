@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use {Frame,Observer,REQUEST_LEN,RESPONSE_LEN};
+
+/// How many of the most recent round trip times are kept for the latency percentiles
+const LATENCY_WINDOW: usize = 1024;
+
+#[derive(Debug,Default)]
+struct Counters{
+    requests: u64,
+    responses: u64,
+    crc_errors: u64,
+    timeouts: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    round_trips: u64,
+    latencies: VecDeque<Duration>,
+}
+
+/// Collects statistics about the quality of a connection
+///
+/// Counts frames and bytes as an `Observer` of the codec, and round trip times and timeouts as an
+/// `Observer` of a `Dispatcher`. To get both, attach the same instance to each, e.g.
+/// `AuroraProto::observed(stats.clone())` and `ClientHandle::with_stats(service,stats)`.
+#[derive(Debug,Default)]
+pub struct ConnectionStats{
+    counters: Mutex<Counters>,
+}
+
+/// A snapshot of `ConnectionStats`
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Stats{
+    pub requests: u64,
+    pub responses: u64,
+    pub crc_errors: u64,
+    pub timeouts: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Requests answered, as recorded by `record_latency`
+    pub round_trips: u64,
+    /// Round trip time percentiles over the recent requests, `None` until a request completes
+    pub latency_p50: Option<Duration>,
+    pub latency_p90: Option<Duration>,
+    pub latency_p99: Option<Duration>,
+}
+
+impl Stats{
+    /// The fraction of received frames which failed their crc check, `None` until a frame is read
+    ///
+    /// Frames are only counted when these stats are attached to the codec, so this stays `None`
+    /// for stats which only observe a `Dispatcher`.
+    pub fn crc_error_rate(&self) -> Option<f64>
+    {
+        ratio(self.crc_errors,self.responses + self.crc_errors)
+    }
+
+    /// The fraction of requests which timed out rather than being answered, `None` until a request
+    /// completes
+    ///
+    /// Requests which failed some other way, like a crc error, are left out.
+    pub fn timeout_rate(&self) -> Option<f64>
+    {
+        ratio(self.timeouts,self.round_trips + self.timeouts)
+    }
+}
+
+fn ratio(count: u64, total: u64) -> Option<f64>
+{
+    if total == 0 {
+        None
+    }else{
+        Some(count as f64 / total as f64)
+    }
+}
+
+fn percentile(sorted: &[Duration], pct: usize) -> Option<Duration>
+{
+    if sorted.is_empty() {
+        return None;
+    }
+    let idx = (sorted.len() * pct).div_ceil(100).saturating_sub(1);
+    Some(sorted[idx])
+}
+
+impl ConnectionStats{
+    pub fn new() -> ConnectionStats
+    {
+        ConnectionStats::default()
+    }
+
+    /// Records how long a request took to be answered
+    pub fn record_latency(&self, latency: Duration)
+    {
+        let mut counters = self.counters.lock().unwrap();
+        counters.round_trips += 1;
+        if counters.latencies.len() == LATENCY_WINDOW {
+            counters.latencies.pop_front();
+        }
+        counters.latencies.push_back(latency);
+    }
+
+    pub fn snapshot(&self) -> Stats
+    {
+        let counters = self.counters.lock().unwrap();
+        let mut sorted = counters.latencies.iter().cloned().collect::<Vec<_>>();
+        sorted.sort();
+        Stats{
+            requests: counters.requests,
+            responses: counters.responses,
+            crc_errors: counters.crc_errors,
+            timeouts: counters.timeouts,
+            bytes_in: counters.bytes_in,
+            bytes_out: counters.bytes_out,
+            round_trips: counters.round_trips,
+            latency_p50: percentile(&sorted,50),
+            latency_p90: percentile(&sorted,90),
+            latency_p99: percentile(&sorted,99),
+        }
+    }
+}
+
+impl Observer for ConnectionStats{
+    fn frame_sent(&self, _frame: &Frame)
+    {
+        let mut counters = self.counters.lock().unwrap();
+        counters.requests += 1;
+        counters.bytes_out += REQUEST_LEN as u64;
+    }
+
+    fn frame_received(&self, _packet: &[u8])
+    {
+        let mut counters = self.counters.lock().unwrap();
+        counters.responses += 1;
+        counters.bytes_in += RESPONSE_LEN as u64;
+    }
+
+    fn crc_error(&self, _packet: &[u8])
+    {
+        let mut counters = self.counters.lock().unwrap();
+        counters.crc_errors += 1;
+        counters.bytes_in += RESPONSE_LEN as u64;
+    }
+
+    fn response_received(&self, _address: u8, latency: Duration)
+    {
+        self.record_latency(latency);
+    }
+
+    fn timeout(&self, _address: u8)
+    {
+        self.counters.lock().unwrap().timeouts += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_percentiles_and_rates() {
+        let stats = ConnectionStats::new();
+        assert_eq!(stats.snapshot().latency_p50,None);
+        assert_eq!(stats.snapshot().crc_error_rate(),None);
+        for ms in 1..101 {
+            stats.frame_sent(&Frame::new(2,50,[0;6]));
+            stats.frame_received(&[0;RESPONSE_LEN]);
+            stats.record_latency(Duration::from_millis(ms));
+        }
+        stats.crc_error(&[0;RESPONSE_LEN]);
+        stats.timeout(2);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.latency_p50,Some(Duration::from_millis(50)));
+        assert_eq!(snapshot.latency_p99,Some(Duration::from_millis(99)));
+        assert_eq!(snapshot.bytes_out,100 * REQUEST_LEN as u64);
+        assert_eq!(snapshot.bytes_in,101 * RESPONSE_LEN as u64);
+        assert!((snapshot.crc_error_rate().unwrap() - 1.0 / 101.0).abs() < 1e-9);
+        assert!((snapshot.timeout_rate().unwrap() - 1.0 / 101.0).abs() < 1e-9);
+    }
+}
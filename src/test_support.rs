@@ -0,0 +1,109 @@
+//! Property testing support for the protocol types
+//!
+//! All the public enums implement `arbitrary::Arbitrary` when the `test-support` feature is on,
+//! and this module provides the equivalent `proptest` strategies.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use {Request,Response,CumulativeDuration};
+use {TransmissionState,GlobalState,InverterState,DcDcState,MeasurementType};
+
+pub fn transmission_state() -> impl Strategy<Value = TransmissionState>
+{
+    select(TransmissionState::VARIANTS)
+}
+
+pub fn global_state() -> impl Strategy<Value = GlobalState>
+{
+    select(GlobalState::VARIANTS)
+}
+
+pub fn inverter_state() -> impl Strategy<Value = InverterState>
+{
+    select(InverterState::VARIANTS)
+}
+
+pub fn dcdc_state() -> impl Strategy<Value = DcDcState>
+{
+    select(DcDcState::VARIANTS)
+}
+
+pub fn measurement_type() -> impl Strategy<Value = MeasurementType>
+{
+    select(MeasurementType::VARIANTS)
+}
+
+pub fn cumulative_duration() -> impl Strategy<Value = CumulativeDuration>
+{
+    select(vec![
+        CumulativeDuration::Daily,
+        CumulativeDuration::Weekly,
+        CumulativeDuration::Monthly,
+        CumulativeDuration::Yearly,
+        CumulativeDuration::Total,
+        CumulativeDuration::SinceReset,
+    ])
+}
+
+pub fn request() -> impl Strategy<Value = Request>
+{
+    prop_oneof![
+        Just(Request::State),
+        Just(Request::PartNumber),
+        Just(Request::Version),
+        (measurement_type(),any::<bool>()).prop_map(|(type_,global)| Request::Measure{type_,global}),
+        Just(Request::SerialNumber),
+        Just(Request::ManufactureDate),
+        cumulative_duration().prop_map(Request::CumulativeEnergy),
+        (any::<u8>(),any::<[u8;6]>()).prop_map(|(opcode,payload)| Request::Raw{opcode,payload}),
+    ]
+}
+
+pub fn response() -> impl Strategy<Value = Response>
+{
+    prop_oneof![
+        (transmission_state(),global_state(),inverter_state(),dcdc_state(),dcdc_state(),any::<u8>())
+            .prop_map(|(trans,global,inverter,dc1,dc2,alarm)| Response::State{trans,global,inverter,dc1,dc2,alarm}),
+        any::<[u8;6]>().prop_map(Response::PartNumber),
+        (transmission_state(),global_state(),any::<[u8;4]>())
+            .prop_map(|(trans,global,par)| Response::Version{trans,global,par1: par[0],par2: par[1],par3: par[2],par4: par[3]}),
+        (transmission_state(),global_state(),any::<f32>(),measurement_type())
+            .prop_map(|(trans,global,val,type_)| Response::Measure{trans,global,val,type_}),
+        any::<[u8;6]>().prop_map(Response::SerialNumber),
+        (transmission_state(),global_state(),any::<[u8;2]>(),any::<[u8;2]>())
+            .prop_map(|(trans,global,week,year)| Response::ManufactureDate{trans,global,week,year}),
+        (transmission_state(),global_state(),any::<u32>(),cumulative_duration())
+            .prop_map(|(trans,global,value,duration)| Response::CumulativeEnergy{trans,global,value,duration}),
+        any::<[u8;6]>().prop_map(Response::Raw),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(req in request(), data in any::<[u8;6]>()) {
+            let _ = Response::decode(&req,data);
+        }
+
+        #[test]
+        fn requests_roundtrip(req in request(), addr in any::<u8>()) {
+            let (decoded_addr,decoded) = Request::decode(&req.encode(addr)).unwrap();
+            prop_assert_eq!(decoded_addr,addr);
+            // raw requests for known commands come back as the known command
+            if let Request::Raw{..} = req {
+                prop_assert_eq!(decoded.encode(addr),req.encode(addr));
+            }else{
+                prop_assert_eq!(decoded,req);
+            }
+        }
+
+        #[test]
+        fn requests_encode_to_their_address(req in request(), addr in any::<u8>()) {
+            prop_assert_eq!(req.encode(addr)[0],addr);
+        }
+    }
+}
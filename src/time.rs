@@ -0,0 +1,87 @@
+//! Conversions for the inverter's timestamps, which count seconds since 2000-01-01 00:00:00
+//!
+//! The inverter clock is a plain wall clock: it has no notion of time zones or daylight saving,
+//! so a timestamp is whatever local time the clock was set to, and converting it to an instant
+//! needs the time zone that was used to set it. Around daylight saving changes a wall clock
+//! time can be ambiguous or not exist at all, hence `LocalResult`.
+
+use std::convert::TryFrom;
+
+use chrono::{DateTime,LocalResult,NaiveDate,NaiveDateTime,TimeDelta,TimeZone};
+
+/// 2000-01-01 00:00:00, the inverter's epoch
+pub fn epoch() -> NaiveDateTime
+{
+    NaiveDate::from_ymd_opt(2000,1,1).unwrap().and_hms_opt(0,0,0).unwrap()
+}
+
+/// The wall clock time of an inverter timestamp
+pub fn to_naive(secs: u32) -> NaiveDateTime
+{
+    epoch() + TimeDelta::seconds(i64::from(secs))
+}
+
+/// The inverter timestamp for a wall clock time, or `None` if it's out of the inverter's range
+pub fn from_naive(time: &NaiveDateTime) -> Option<u32>
+{
+    // num_seconds truncates towards zero, which would put the last second before the epoch at 0
+    if *time < epoch() {
+        return None;
+    }
+    u32::try_from((*time - epoch()).num_seconds()).ok()
+}
+
+/// The instant of an inverter timestamp, for an inverter whose clock was set to local time in `tz`
+pub fn to_datetime<Tz: TimeZone>(secs: u32, tz: &Tz) -> LocalResult<DateTime<Tz>>
+{
+    tz.from_local_datetime(&to_naive(secs))
+}
+
+/// The inverter timestamp for an instant, as local time in the instant's time zone
+pub fn from_datetime<Tz: TimeZone>(time: &DateTime<Tz>) -> Option<u32>
+{
+    from_naive(&time.naive_local())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset,Utc};
+
+    #[test]
+    fn epoch_is_2000() {
+        assert_eq!(to_naive(0),epoch());
+        assert_eq!(to_naive(86_400),NaiveDate::from_ymd_opt(2000,1,2).unwrap().and_hms_opt(0,0,0).unwrap());
+        assert_eq!(from_naive(&(epoch() - TimeDelta::seconds(1))),None);
+        assert_eq!(from_naive(&(epoch() - TimeDelta::milliseconds(500))),None);
+        assert_eq!(from_naive(&(epoch() + TimeDelta::milliseconds(1500))),Some(1));
+    }
+
+    #[test]
+    fn timestamps_are_wall_clock_time() {
+        let tz = FixedOffset::east_opt(3600).unwrap();
+        let secs = 600_000_000;
+        let local = to_datetime(secs,&tz).unwrap();
+        assert_eq!(from_datetime(&local),Some(secs));
+        // the same instant in utc is an hour earlier on the wall clock
+        assert_eq!(from_datetime(&local.with_timezone(&Utc)),Some(secs - 3600));
+    }
+
+    #[test]
+    fn daylight_saving_changes() {
+        use chrono_tz::Europe::Berlin;
+        let secs = |y,m,d,h,min| from_naive(&NaiveDate::from_ymd_opt(y,m,d).unwrap().and_hms_opt(h,min,0).unwrap()).unwrap();
+        // the clocks skip from 02:00 to 03:00, so 02:30 never happens
+        assert_eq!(to_datetime(secs(2021,3,28,2,30),&Berlin),LocalResult::None);
+        // and go back from 03:00 to 02:00, so 02:30 happens twice
+        match to_datetime(secs(2021,10,31,2,30),&Berlin) {
+            LocalResult::Ambiguous(summer,winter) => {
+                assert_eq!(winter - summer,TimeDelta::hours(1));
+                assert_eq!(from_datetime(&summer),from_datetime(&winter));
+            }
+            other => panic!("expected an ambiguous time, got {:?}",other),
+        }
+        let noon = secs(2021,7,1,12,0);
+        assert_eq!(from_datetime(&to_datetime(noon,&Berlin).unwrap()),Some(noon));
+    }
+}